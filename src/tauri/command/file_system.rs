@@ -0,0 +1,772 @@
+use super::ftp::with_ftp_client;
+use super::ssh::{get_session, write_all_retrying, SftpClient};
+use super::tree::{join_remote, local_mode, sftp_create_dir_all};
+use crate::toolbox::string::{decode_text, encode_text};
+use serde::{Deserialize, Serialize};
+use ssh2::FileStat;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+// chunks in flight between the two ends of a cross-backend copy
+const PIPE_DEPTH: usize = 16;
+
+const COPY_PROGRESS_EVENT: &str = "fs_copy_progress";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+const STATUS_COPYING: &str = "copying";
+const STATUS_SUCCESS: &str = "success";
+const STATUS_FAILURE: &str = "failure";
+const STATUS_CANCELLED: &str = "cancelled";
+
+lazy_static! {
+    static ref FS_COPIES: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FsEntry {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    // unix seconds, 0 when unknown
+    pub modified: u64,
+    // true for symlinks that point at a directory as well
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub mode: Option<u32>,
+}
+
+// the operations a file manager pane needs, implemented for local disk, SFTP
+// and FTP; paths are always in the backend's own syntax
+pub trait FileSystem {
+    fn list(&mut self, path: &str) -> Result<Vec<FsEntry>, String>;
+    fn stat(&mut self, path: &str) -> Result<FsEntry, String>;
+    // creates missing parents too
+    fn mkdir(&mut self, path: &str) -> Result<(), String>;
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), String>;
+    fn delete(&mut self, path: &str, recursive: bool) -> Result<(), String>;
+    // streams the file into `sink`, returns the number of bytes
+    fn read_stream(&mut self, path: &str, sink: &mut dyn Write) -> Result<u64, String>;
+    // creates or truncates the file and fills it from `source`
+    fn write_stream(&mut self, path: &str, source: &mut dyn Read) -> Result<u64, String>;
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        self.read_stream(path, &mut data)?;
+        Ok(data)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<u64, String> {
+        self.write_stream(path, &mut Cursor::new(data))
+    }
+}
+
+pub fn file_name(path: &str) -> String {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+// "local", "ssh:<session key>" or "ftp:<connection key>"
+#[derive(Debug, Clone, PartialEq)]
+enum Backend {
+    Local,
+    Ssh(String),
+    Ftp(String),
+}
+
+impl Backend {
+    fn parse(connection_id: &str) -> Result<Backend, String> {
+        match connection_id.split_once(':') {
+            None if connection_id == "local" => Ok(Backend::Local),
+            Some(("ssh", key)) => Ok(Backend::Ssh(key.to_string())),
+            Some(("ftp", key)) => Ok(Backend::Ftp(key.to_string())),
+            _ => Err(format!("unknown connection id:{}", connection_id)),
+        }
+    }
+}
+
+// an opened backend; operations that make many calls, like a tree copy, keep
+// one so that SFTP is set up once rather than per call
+enum FsHandle {
+    Local,
+    Sftp(SftpFs),
+    Ftp(String),
+}
+
+impl FsHandle {
+    fn open(backend: &Backend) -> Result<FsHandle, String> {
+        Ok(match backend {
+            Backend::Local => FsHandle::Local,
+            Backend::Ssh(key) => FsHandle::Sftp(SftpFs::open(key)?),
+            Backend::Ftp(key) => FsHandle::Ftp(key.clone()),
+        })
+    }
+
+    fn with<T, F>(&mut self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut dyn FileSystem) -> Result<T, String>,
+    {
+        match self {
+            FsHandle::Local => f(&mut LocalFs),
+            FsHandle::Sftp(sftp) => f(sftp),
+            FsHandle::Ftp(key) => with_ftp_client(key, |client| f(client)),
+        }
+    }
+}
+
+fn with_fs<T, F>(backend: &Backend, f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut dyn FileSystem) -> Result<T, String>,
+{
+    FsHandle::open(backend)?.with(f)
+}
+
+pub struct LocalFs;
+
+fn local_entry(path: &Path) -> Result<FsEntry, String> {
+    let link = fs::symlink_metadata(path).map_err(|e| format!("stat error:{}", e))?;
+    // a dangling link is still listed, as the link itself
+    let metadata = fs::metadata(path).unwrap_or_else(|_| link.clone());
+    Ok(FsEntry {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        path: path.to_string_lossy().to_string(),
+        size: metadata.len(),
+        modified: metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        is_dir: metadata.is_dir(),
+        is_symlink: link.file_type().is_symlink(),
+        mode: Some(local_mode(&metadata)),
+    })
+}
+
+impl FileSystem for LocalFs {
+    fn list(&mut self, path: &str) -> Result<Vec<FsEntry>, String> {
+        let dir = fs::read_dir(path).map_err(|e| format!("read dir error:{}", e))?;
+        let mut entries = Vec::new();
+        for item in dir {
+            let item = item.map_err(|e| format!("read dir error:{}", e))?;
+            entries.push(local_entry(&item.path())?);
+        }
+        Ok(entries)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FsEntry, String> {
+        local_entry(Path::new(path))
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<(), String> {
+        fs::create_dir_all(path).map_err(|e| format!("mkdir error:{}", e))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), String> {
+        fs::rename(from, to).map_err(|e| format!("rename error:{}", e))
+    }
+
+    fn delete(&mut self, path: &str, recursive: bool) -> Result<(), String> {
+        let metadata = fs::symlink_metadata(path).map_err(|e| format!("stat error:{}", e))?;
+        let result = match (metadata.is_dir(), recursive) {
+            (false, _) => fs::remove_file(path),
+            (true, false) => fs::remove_dir(path),
+            (true, true) => fs::remove_dir_all(path),
+        };
+        result.map_err(|e| format!("delete error:{}", e))
+    }
+
+    fn read_stream(&mut self, path: &str, sink: &mut dyn Write) -> Result<u64, String> {
+        let mut file = fs::File::open(path).map_err(|e| format!("open error:{}", e))?;
+        io::copy(&mut file, sink).map_err(|e| format!("read error:{}", e))
+    }
+
+    fn write_stream(&mut self, path: &str, source: &mut dyn Read) -> Result<u64, String> {
+        let mut file = fs::File::create(path).map_err(|e| format!("create error:{}", e))?;
+        io::copy(source, &mut file).map_err(|e| format!("write error:{}", e))
+    }
+}
+
+pub struct SftpFs {
+    sftp: SftpClient,
+}
+
+impl SftpFs {
+    pub fn open(session_key: &str) -> Result<SftpFs, String> {
+        let sftp = SftpClient::open(&get_session(session_key)?)?;
+        Ok(SftpFs { sftp })
+    }
+
+    fn entry(&self, path: &str, stat: &FileStat) -> FsEntry {
+        let is_symlink = stat.file_type().is_symlink();
+        // follow links so directories behind them can be opened
+        let target = match is_symlink {
+            true => self.sftp.call(|s| s.stat(Path::new(path))).ok(),
+            false => None,
+        };
+        let stat = target.as_ref().unwrap_or(stat);
+        FsEntry {
+            name: file_name(path),
+            path: path.to_string(),
+            size: stat.size.unwrap_or(0),
+            modified: stat.mtime.unwrap_or(0),
+            is_dir: stat.is_dir(),
+            is_symlink,
+            mode: stat.perm.map(|perm| perm & 0o7777),
+        }
+    }
+
+    fn remove_tree(&self, path: &str) -> Result<(), String> {
+        let list = self
+            .sftp
+            .call(|s| s.readdir(Path::new(path)))
+            .map_err(|e| format!("read dir {} error:{}", path, e))?;
+        for (child, stat) in list {
+            // links are removed, never followed
+            if stat.is_dir() {
+                self.remove_tree(&child.to_string_lossy())?;
+            } else {
+                self.sftp
+                    .call(|s| s.unlink(&child))
+                    .map_err(|e| format!("delete {} error:{}", child.display(), e))?;
+            }
+        }
+        self.sftp
+            .call(|s| s.rmdir(Path::new(path)))
+            .map_err(|e| format!("delete {} error:{}", path, e))
+    }
+}
+
+impl FileSystem for SftpFs {
+    fn list(&mut self, path: &str) -> Result<Vec<FsEntry>, String> {
+        let list = self
+            .sftp
+            .call(|s| s.readdir(Path::new(path)))
+            .map_err(|e| format!("read dir error:{}", e))?;
+        Ok(list
+            .iter()
+            .map(|(child, stat)| self.entry(&child.to_string_lossy(), stat))
+            .collect())
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FsEntry, String> {
+        let stat = self
+            .sftp
+            .call(|s| s.lstat(Path::new(path)))
+            .map_err(|e| format!("stat error:{}", e))?;
+        Ok(self.entry(path, &stat))
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<(), String> {
+        sftp_create_dir_all(&self.sftp, path, 0o755)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), String> {
+        self.sftp
+            .call(|s| s.rename(Path::new(from), Path::new(to), None))
+            .map_err(|e| format!("rename error:{}", e))
+    }
+
+    fn delete(&mut self, path: &str, recursive: bool) -> Result<(), String> {
+        let stat = self
+            .sftp
+            .call(|s| s.lstat(Path::new(path)))
+            .map_err(|e| format!("stat error:{}", e))?;
+        match (stat.is_dir(), recursive) {
+            (false, _) => self
+                .sftp
+                .call(|s| s.unlink(Path::new(path)))
+                .map_err(|e| format!("delete error:{}", e)),
+            (true, false) => self
+                .sftp
+                .call(|s| s.rmdir(Path::new(path)))
+                .map_err(|e| format!("delete error:{}", e)),
+            (true, true) => self.remove_tree(path),
+        }
+    }
+
+    fn read_stream(&mut self, path: &str, sink: &mut dyn Write) -> Result<u64, String> {
+        let mut file = self
+            .sftp
+            .open_file(|s| s.open(Path::new(path)))
+            .map_err(|e| format!("open error:{}", e))?;
+        let mut file = self.sftp.stream(&mut file);
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut total = 0;
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => return Ok(total),
+                Ok(size) => {
+                    sink.write_all(&buffer[..size])
+                        .map_err(|e| format!("write error:{}", e))?;
+                    total += size as u64;
+                }
+                Err(err) => return Err(format!("read error:{}", err)),
+            }
+        }
+    }
+
+    fn write_stream(&mut self, path: &str, source: &mut dyn Read) -> Result<u64, String> {
+        let mut file = self
+            .sftp
+            .open_file(|s| s.create(Path::new(path)))
+            .map_err(|e| format!("create error:{}", e))?;
+        let mut file = self.sftp.stream(&mut file);
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut total = 0;
+        loop {
+            let size = match source.read(&mut buffer) {
+                Ok(0) => return Ok(total),
+                Ok(size) => size,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(format!("read error:{}", err)),
+            };
+            write_all_retrying(&mut file, &buffer[..size])
+                .map_err(|e| format!("write error:{}", e))?;
+            total += size as u64;
+        }
+    }
+}
+
+// carries file data from the thread reading the source to the one writing
+// the destination; an error on either side ends the other one
+struct PipeWriter(SyncSender<Result<Vec<u8>, String>>);
+struct PipeReader {
+    receiver: Receiver<Result<Vec<u8>, String>>,
+    chunk: Vec<u8>,
+    offset: usize,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Ok(data.to_vec()))
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.offset >= self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                }
+                Ok(Err(err)) => return Err(io::Error::other(err)),
+                // the sender is gone once the source was read completely
+                Err(_) => return Ok(0),
+            }
+        }
+        let size = buffer.len().min(self.chunk.len() - self.offset);
+        buffer[..size].copy_from_slice(&self.chunk[self.offset..self.offset + size]);
+        self.offset += size;
+        Ok(size)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FsCopyProgress {
+    copy_id: String,
+    from_path: String,
+    to_path: String,
+    current_file: String,
+    files_done: u64,
+    transferred: u64,
+    status: String,
+    message: String,
+}
+
+// reports "fs_copy_progress" events and carries the cancel flag of one copy
+struct CopyProgress {
+    app: AppHandle,
+    event: FsCopyProgress,
+    cancel: Arc<AtomicBool>,
+    last_emit: Instant,
+}
+
+impl CopyProgress {
+    fn new(
+        app: AppHandle,
+        copy_id: Option<String>,
+        from_path: &str,
+        to_path: &str,
+    ) -> Result<CopyProgress, String> {
+        let copy_id = copy_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let cancel = Arc::new(AtomicBool::new(false));
+        FS_COPIES
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(copy_id.clone(), cancel.clone());
+        Ok(CopyProgress {
+            app,
+            event: FsCopyProgress {
+                copy_id,
+                from_path: from_path.to_string(),
+                to_path: to_path.to_string(),
+                current_file: String::new(),
+                files_done: 0,
+                transferred: 0,
+                status: String::from(STATUS_COPYING),
+                message: String::new(),
+            },
+            cancel,
+            last_emit: Instant::now(),
+        })
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    fn checkpoint(&self) -> Result<(), String> {
+        match self.cancelled() {
+            true => Err(String::from(STATUS_CANCELLED)),
+            false => Ok(()),
+        }
+    }
+
+    fn start_file(&mut self, path: &str) {
+        self.event.current_file = path.to_string();
+        self.emit();
+    }
+
+    fn advance(&mut self, size: u64) {
+        self.event.transferred += size;
+        if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+            self.emit();
+        }
+    }
+
+    fn finish_file(&mut self) {
+        self.event.files_done += 1;
+    }
+
+    fn emit(&mut self) {
+        self.last_emit = Instant::now();
+        let _ = self.app.emit(COPY_PROGRESS_EVENT, self.event.clone());
+    }
+
+    fn finish(mut self, result: Result<u64, String>) -> Result<u64, String> {
+        // whichever end noticed the cancel first, it is reported as such
+        let result = match result {
+            Err(_) if self.cancelled() => Err(String::from(STATUS_CANCELLED)),
+            result => result,
+        };
+        self.event.status = match &result {
+            Ok(_) => String::from(STATUS_SUCCESS),
+            Err(_) if self.cancelled() => String::from(STATUS_CANCELLED),
+            Err(_) => String::from(STATUS_FAILURE),
+        };
+        if let Err(err) = &result {
+            self.event.message = err.clone();
+        }
+        self.emit();
+        result
+    }
+}
+
+impl Drop for CopyProgress {
+    fn drop(&mut self) {
+        if let Ok(mut copies) = FS_COPIES.lock() {
+            copies.remove(&self.event.copy_id);
+        }
+    }
+}
+
+// what the destination reads goes through here, counting bytes and failing
+// once the copy is cancelled
+struct CountingReader<'a, R: Read> {
+    inner: R,
+    progress: &'a mut CopyProgress,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.progress.cancelled() {
+            return Err(io::Error::other(STATUS_CANCELLED));
+        }
+        let size = self.inner.read(buffer)?;
+        self.progress.advance(size as u64);
+        Ok(size)
+    }
+}
+
+// both ends of a copy, each opened once for the whole tree
+struct Copier {
+    same_ftp: bool,
+    from: FsHandle,
+    to: FsHandle,
+}
+
+impl Copier {
+    fn open(from: &Backend, to: &Backend) -> Result<Copier, String> {
+        Ok(Copier {
+            same_ftp: from == to && matches!(from, Backend::Ftp(_)),
+            from: FsHandle::open(from)?,
+            to: FsHandle::open(to)?,
+        })
+    }
+
+    fn copy_file(
+        &mut self,
+        progress: &mut CopyProgress,
+        from_path: &str,
+        to_path: &str,
+    ) -> Result<(), String> {
+        progress.start_file(from_path);
+        // an FTP connection can only run one transfer at a time, so a copy
+        // within the same one goes through a local temp file
+        if self.same_ftp {
+            let temp = std::env::temp_dir().join(format!("rust_box_copy_{}", Uuid::new_v4()));
+            let result = self.from.with(|ftp| {
+                let mut file = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create_new(true)
+                    .open(&temp)
+                    .map_err(|e| format!("temp file error:{}", e))?;
+                ftp.read_stream(from_path, &mut file)?;
+                file.seek(SeekFrom::Start(0))
+                    .map_err(|e| format!("temp file error:{}", e))?;
+                let mut source = CountingReader {
+                    inner: file,
+                    progress: &mut *progress,
+                };
+                ftp.write_stream(to_path, &mut source).map(|_| ())
+            });
+            let _ = fs::remove_file(&temp);
+            result?;
+            progress.finish_file();
+            return Ok(());
+        }
+        let (sender, receiver) = sync_channel(PIPE_DEPTH);
+        let from = &mut self.from;
+        let to = &mut self.to;
+        thread::scope(|scope| {
+            let reader = scope.spawn(move || {
+                let mut pipe = PipeWriter(sender);
+                let result = from.with(|fs| fs.read_stream(from_path, &mut pipe));
+                if let Err(err) = &result {
+                    let _ = pipe.0.send(Err(err.clone()));
+                }
+                result
+            });
+            let mut source = CountingReader {
+                inner: PipeReader {
+                    receiver,
+                    chunk: Vec::new(),
+                    offset: 0,
+                },
+                progress: &mut *progress,
+            };
+            let written = to.with(|fs| fs.write_stream(to_path, &mut source));
+            // unblocks the reader if the destination gave up early
+            drop(source);
+            let read = reader
+                .join()
+                .map_err(|_| String::from("copy thread panicked"))?;
+            read.and(written).map(|_| ())
+        })?;
+        progress.finish_file();
+        Ok(())
+    }
+
+    fn copy_tree(
+        &mut self,
+        progress: &mut CopyProgress,
+        from_path: &str,
+        to_path: &str,
+    ) -> Result<u64, String> {
+        progress.checkpoint()?;
+        let entry = self.from.with(|fs| fs.stat(from_path))?;
+        if !entry.is_dir {
+            self.copy_file(progress, from_path, to_path)?;
+            return Ok(1);
+        }
+        self.to.with(|fs| fs.mkdir(to_path))?;
+        let children = self.from.with(|fs| fs.list(from_path))?;
+        let mut count = 0;
+        for child in children {
+            // linked directories are skipped to stay clear of cycles
+            if child.is_dir && child.is_symlink {
+                continue;
+            }
+            let target = join_remote(to_path, &child.name);
+            count += self.copy_tree(progress, &child.path, &target)?;
+        }
+        Ok(count)
+    }
+}
+
+// a directory copied into itself would keep finding what it just wrote
+fn check_not_inside(
+    from: &Backend,
+    from_path: &str,
+    to: &Backend,
+    to_path: &str,
+) -> Result<(), String> {
+    if from != to {
+        return Ok(());
+    }
+    let (source, target) = match from {
+        // resolves `..` and links; the target itself usually does not exist
+        Backend::Local => {
+            let source = fs::canonicalize(from_path).map_err(|e| format!("stat error:{}", e))?;
+            let target = Path::new(to_path);
+            let target = match (target.parent(), target.file_name()) {
+                (Some(parent), Some(name)) => fs::canonicalize(parent)
+                    .map(|parent| parent.join(name))
+                    .unwrap_or_else(|_| target.to_path_buf()),
+                _ => target.to_path_buf(),
+            };
+            (source, target)
+        }
+        _ => (PathBuf::from(from_path), PathBuf::from(to_path)),
+    };
+    if target.starts_with(&source) {
+        return Err(format!("cannot copy {} into itself", from_path));
+    }
+    Ok(())
+}
+
+async fn run_fs<T, F>(connection_id: String, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn FileSystem) -> Result<T, String> + Send + 'static,
+{
+    let backend = Backend::parse(&connection_id)?;
+    tokio::task::spawn_blocking(move || with_fs(&backend, f))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FsContent {
+    path: String,
+    content: String,
+    encoding: String,
+    size: u64,
+}
+
+#[tauri::command]
+pub async fn fs_list(connection_id: String, path: String) -> Result<Vec<FsEntry>, String> {
+    let mut entries = run_fs(connection_id, move |fs| fs.list(&path)).await?;
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+#[tauri::command]
+pub async fn fs_stat(connection_id: String, path: String) -> Result<FsEntry, String> {
+    run_fs(connection_id, move |fs| fs.stat(&path)).await
+}
+
+#[tauri::command]
+pub async fn fs_read(
+    connection_id: String,
+    path: String,
+    encoding: Option<String>,
+) -> Result<FsContent, String> {
+    run_fs(connection_id, move |fs| {
+        let data = fs.read(&path)?;
+        let (content, encoding) = decode_text(&data, encoding.as_deref())?;
+        Ok(FsContent {
+            path,
+            content,
+            encoding,
+            size: data.len() as u64,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn fs_write(
+    connection_id: String,
+    path: String,
+    content: String,
+    encoding: Option<String>,
+) -> Result<u64, String> {
+    let data = encode_text(&content, encoding.as_deref())?;
+    run_fs(connection_id, move |fs| fs.write(&path, &data)).await
+}
+
+#[tauri::command]
+pub async fn fs_mkdir(connection_id: String, path: String) -> Result<(), String> {
+    run_fs(connection_id, move |fs| fs.mkdir(&path)).await
+}
+
+#[tauri::command]
+pub async fn fs_rename(connection_id: String, from: String, to: String) -> Result<(), String> {
+    run_fs(connection_id, move |fs| fs.rename(&from, &to)).await
+}
+
+#[tauri::command]
+pub async fn fs_delete(
+    connection_id: String,
+    path: String,
+    recursive: Option<bool>,
+) -> Result<(), String> {
+    run_fs(connection_id, move |fs| {
+        fs.delete(&path, recursive.unwrap_or(false))
+    })
+    .await
+}
+
+// copies a file or a whole directory between any two connections, returns
+// the number of files copied; progress goes out as "fs_copy_progress" events
+// under `copy_id`, which cancel_fs_copy takes
+#[tauri::command]
+pub async fn fs_copy(
+    app: AppHandle,
+    from_id: String,
+    from_path: String,
+    to_id: String,
+    to_path: String,
+    copy_id: Option<String>,
+) -> Result<u64, String> {
+    let from = Backend::parse(&from_id)?;
+    let to = Backend::parse(&to_id)?;
+    check_not_inside(&from, &from_path, &to, &to_path)?;
+    let mut progress = CopyProgress::new(app, copy_id, &from_path, &to_path)?;
+    tokio::task::spawn_blocking(move || {
+        let result = Copier::open(&from, &to)
+            .and_then(|mut copier| copier.copy_tree(&mut progress, &from_path, &to_path));
+        progress.finish(result)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// the file being copied is left as far as it got
+#[tauri::command]
+pub async fn cancel_fs_copy(copy_id: String) -> Result<(), String> {
+    FS_COPIES
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&copy_id)
+        .ok_or("no copy")?
+        .store(true, Ordering::SeqCst);
+    Ok(())
+}
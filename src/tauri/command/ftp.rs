@@ -1,124 +1,1108 @@
-use std::io::Write;
-use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use suppaftp::FtpStream;
-use tokio::io::AsyncReadExt;
+use super::file_system::{file_name, FileSystem, FsEntry};
+use super::ssh_transfer::{DIRECTION_DOWNLOAD, DIRECTION_UPLOAD};
+use super::tree::{join_remote, walk_local};
+use crate::toolbox::file::PathFilter;
+use crate::toolbox::ftp_list::{is_safe_entry_name, parse_list_line, parse_mlsd_line, FtpEntry};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+use suppaftp::native_tls::{Certificate, TlsConnector};
+use suppaftp::types::{FileType, FormatControl};
+use suppaftp::{FtpError, FtpResult, FtpStream, Mode, NativeTlsConnector, NativeTlsFtpStream};
+use tauri::{AppHandle, Emitter};
 use uuid::Uuid;
 lazy_static::lazy_static! {
-    static ref FTP_CLIENTS: Arc<Mutex<std::collections::HashMap<String, FtpStream>>> =
+    static ref FTP_CLIENTS: Arc<Mutex<std::collections::HashMap<String, Arc<FtpConnection>>>> =
+        Arc::new(Mutex::new(std::collections::HashMap::new()));
+    static ref FTP_TRANSFERS: Arc<Mutex<std::collections::HashMap<String, Arc<AtomicBool>>>> =
         Arc::new(Mutex::new(std::collections::HashMap::new()));
 }
 
-#[tauri::command]
-pub async fn connect_ftp(
+const FTP_PROGRESS_EVENT: &str = "ftp_transfer_progress";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const BUFFER_SIZE: usize = 64 * 1024;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const KEEPALIVE_TICK: Duration = Duration::from_secs(5);
+// seconds of silence before a NOOP is sent
+const KEEPALIVE_INTERVAL: u64 = 30;
+
+const STATUS_TRANSFERRING: &str = "transferring";
+const STATUS_SUCCESS: &str = "success";
+const STATUS_FAILURE: &str = "failure";
+const STATUS_CANCELLED: &str = "cancelled";
+
+// the second field caches whether the server lists with MLSD, so FEAT is
+// only sent once per login
+pub enum FtpClient {
+    Plain(FtpStream, Option<bool>),
+    Secure(NativeTlsFtpStream, Option<bool>),
+}
+
+// runs the same code against a plain or a TLS stream
+macro_rules! with_ftp {
+    ($client:expr, $ftp:ident => $body:expr) => {
+        match $client {
+            FtpClient::Plain($ftp, _) => $body,
+            FtpClient::Secure($ftp, _) => $body,
+        }
+    };
+}
+
+// like with_ftp!, for builder methods that consume the stream
+macro_rules! map_ftp {
+    ($client:expr, $ftp:ident => $body:expr) => {
+        match $client {
+            FtpClient::Plain($ftp, mlsd) => FtpClient::Plain($body, mlsd),
+            FtpClient::Secure($ftp, mlsd) => FtpClient::Secure($body, mlsd),
+        }
+    };
+}
+
+impl FtpClient {
+    // MLSD gives exact sizes and UTC times, so use it whenever FEAT
+    // advertises MLST; older servers fall back to parsing LIST
+    fn list_entries(&mut self, path: &str) -> FtpResult<Vec<FtpEntry>> {
+        let mlsd = self.supports_mlsd();
+        with_ftp!(self, ftp => {
+            if mlsd {
+                if let Ok(lines) = ftp.mlsd(Some(path)) {
+                    return Ok(lines.iter().filter_map(|line| parse_mlsd_line(line)).collect());
+                }
+            }
+            let lines = ftp.list(Some(path))?;
+            Ok(lines.iter().filter_map(|line| parse_list_line(line)).collect())
+        })
+    }
+
+    fn supports_mlsd(&mut self) -> bool {
+        let (FtpClient::Plain(_, cached) | FtpClient::Secure(_, cached)) = self;
+        if let Some(mlsd) = *cached {
+            return mlsd;
+        }
+        let mlsd = with_ftp!(self, ftp => ftp.feat())
+            .map(|features| features.contains_key("MLST"))
+            .unwrap_or(false);
+        let (FtpClient::Plain(_, cached) | FtpClient::Secure(_, cached)) = self;
+        *cached = Some(mlsd);
+        mlsd
+    }
+
+    // FTP has no stat for directories, so try to enter it and come back
+    fn is_dir(&mut self, path: &str) -> bool {
+        with_ftp!(self, ftp => {
+            let Ok(current) = ftp.pwd() else {
+                return false;
+            };
+            let entered = ftp.cwd(path).is_ok();
+            let _ = ftp.cwd(&current);
+            entered
+        })
+    }
+
+    // like `mkdir -p`: parents that already exist are fine; like remove_tree
+    // it leaves the wording of the error to the caller
+    fn make_dirs(&mut self, path: &str) -> FtpResult<()> {
+        let mut current = String::from(if path.starts_with('/') { "/" } else { "" });
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            current = if current.is_empty() || current == "/" {
+                format!("{}{}", current, part)
+            } else {
+                format!("{}/{}", current, part)
+            };
+            let created = with_ftp!(self, ftp => ftp.mkdir(&current));
+            if let Err(err) = created {
+                if !self.is_dir(&current) {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_tree(&mut self, path: &str) -> FtpResult<()> {
+        for entry in self.list_entries(path)? {
+            let child = join_remote(path, &entry.name);
+            if entry.is_dir && !entry.is_symlink {
+                self.remove_tree(&child)?;
+            } else {
+                with_ftp!(self, ftp => ftp.rm(&child))?;
+            }
+        }
+        with_ftp!(self, ftp => ftp.rmdir(path))
+    }
+
+    fn upload_tree(&mut self, local_dir: &Path, path: &str) -> Result<u64, String> {
+        let filter = PathFilter::new(&[], &[])?;
+        let mut entries = Vec::new();
+        walk_local(local_dir, "", &filter, &mut entries)?;
+        self.make_dirs(path).map_err(|e| format!("创建目录失败: {}", e))?;
+        let mut count = 0;
+        // walk_local lists every directory before its content
+        for entry in entries {
+            let target = join_remote(path, &entry.relative);
+            if entry.is_dir {
+                self.make_dirs(&target).map_err(|e| format!("创建目录失败: {}", e))?;
+                continue;
+            }
+            let mut reader = std::fs::File::open(local_dir.join(&entry.relative))
+                .map_err(|e| format!("打开文件失败: {}", e))?;
+            with_ftp!(self, ftp => ftp.put_file(&target, &mut reader))
+                .map_err(|e| format!("上传文件失败: {}", e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn download_tree(&mut self, path: &str, local_dir: &Path) -> Result<u64, String> {
+        std::fs::create_dir_all(local_dir).map_err(|e| format!("创建目录失败: {}", e))?;
+        let entries = self
+            .list_entries(path)
+            .map_err(|e| format!("获取文件列表失败: {}", e))?;
+        let mut count = 0;
+        // a hostile server could name an entry `../x` to write outside
+        for entry in entries.into_iter().filter(|entry| is_safe_entry_name(&entry.name)) {
+            let source = join_remote(path, &entry.name);
+            let target = local_dir.join(&entry.name);
+            if entry.is_dir && !entry.is_symlink {
+                count += self.download_tree(&source, &target)?;
+                continue;
+            }
+            let mut file =
+                std::fs::File::create(&target).map_err(|e| format!("创建文件失败: {}", e))?;
+            with_ftp!(self, ftp => ftp.retr(&source, |reader| {
+                std::io::copy(reader, &mut file).map_err(FtpError::ConnectionError)
+            }))
+            .map_err(|e| format!("下载文件失败: {}", e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn upload_stream(
+        &mut self,
+        local_file: &Path,
+        path: &str,
+        resume: bool,
+        progress: &mut FtpProgress,
+    ) -> Result<(), String> {
+        let mut reader =
+            std::fs::File::open(local_file).map_err(|e| format!("打开文件失败: {}", e))?;
+        let total = reader
+            .metadata()
+            .map_err(|e| format!("读取文件信息失败: {}", e))?
+            .len();
+        with_ftp!(self, ftp => {
+            let existing = match resume {
+                true => ftp.size(path).map(|size| size as u64).unwrap_or(0),
+                false => 0,
+            };
+            if existing > 0 && existing == total {
+                progress.begin(total, total);
+                return Ok(());
+            }
+            // a remote file larger than ours is not a part of it; the rest is
+            // sent with APPE, as many servers ignore REST before STOR
+            let offset = if existing < total { existing } else { 0 };
+            if offset > 0 {
+                reader
+                    .seek(SeekFrom::Start(offset))
+                    .map_err(|e| format!("读取文件失败: {}", e))?;
+            }
+            progress.begin(offset, total);
+            let mut stream = match offset {
+                0 => ftp.put_with_stream(path),
+                _ => ftp.append_with_stream(path),
+            }
+            .map_err(|e| format!("上传文件失败: {}", e))?;
+            let mut buffer = vec![0u8; BUFFER_SIZE];
+            let mut result = Ok(());
+            while !progress.cancelled() {
+                let size = match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(size) => size,
+                    Err(err) => {
+                        result = Err(format!("读取文件失败: {}", err));
+                        break;
+                    }
+                };
+                if let Err(err) = stream.write_all(&buffer[..size]) {
+                    result = Err(format!("上传文件失败: {}", err));
+                    break;
+                }
+                progress.advance(size as u64);
+            }
+            // closing the data connection keeps what was sent, so a cancelled
+            // upload can be resumed later
+            let finalized = ftp
+                .finalize_put_stream(stream)
+                .map_err(|e| format!("上传文件失败: {}", e));
+            result.and(finalized)?;
+            if progress.cancelled() {
+                return Err(String::from(STATUS_CANCELLED));
+            }
+            Ok(())
+        })
+    }
+
+    fn download_stream(
+        &mut self,
+        path: &str,
+        local_file: &Path,
+        resume: bool,
+        progress: &mut FtpProgress,
+    ) -> Result<(), String> {
+        with_ftp!(self, ftp => {
+            let total = ftp.size(path).map(|size| size as u64).unwrap_or(0);
+            let existing = match resume {
+                true => std::fs::metadata(local_file).map(|meta| meta.len()).unwrap_or(0),
+                false => 0,
+            };
+            if existing > 0 && existing == total {
+                progress.begin(total, total);
+                return Ok(());
+            }
+            let offset = if existing < total { existing } else { 0 };
+            let mut file = if offset > 0 {
+                std::fs::OpenOptions::new()
+                    .append(true)
+                    .open(local_file)
+                    .map_err(|e| format!("打开文件失败: {}", e))?
+            } else {
+                std::fs::File::create(local_file).map_err(|e| format!("创建文件失败: {}", e))?
+            };
+            if offset > 0 {
+                ftp.resume_transfer(offset as usize)
+                    .map_err(|e| format!("续传失败: {}", e))?;
+            }
+            progress.begin(offset, total);
+            let mut stream = ftp
+                .retr_as_stream(path)
+                .map_err(|e| format!("下载文件失败: {}", e))?;
+            let mut buffer = vec![0u8; BUFFER_SIZE];
+            loop {
+                if progress.cancelled() {
+                    let _ = ftp.abort(stream);
+                    return Err(String::from(STATUS_CANCELLED));
+                }
+                let size = match stream.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(size) => size,
+                    Err(err) => {
+                        let _ = ftp.abort(stream);
+                        return Err(format!("下载文件失败: {}", err));
+                    }
+                };
+                if let Err(err) = file.write_all(&buffer[..size]) {
+                    let _ = ftp.abort(stream);
+                    return Err(format!("写入文件失败: {}", err));
+                }
+                progress.advance(size as u64);
+            }
+            ftp.finalize_retr_stream(stream)
+                .map_err(|e| format!("下载文件失败: {}", e))
+        })
+    }
+}
+
+// errors read like the other file_system backends
+impl FileSystem for FtpClient {
+    fn list(&mut self, path: &str) -> Result<Vec<FsEntry>, String> {
+        let entries = self
+            .list_entries(path)
+            .map_err(|e| format!("read dir error:{}", e))?;
+        // copies join these names onto local paths
+        Ok(entries
+            .into_iter()
+            .filter(|entry| is_safe_entry_name(&entry.name))
+            .map(|entry| FsEntry {
+                path: join_remote(path, &entry.name),
+                name: entry.name,
+                size: entry.size,
+                modified: entry.modified,
+                is_dir: entry.is_dir,
+                is_symlink: entry.is_symlink,
+                mode: entry.mode,
+            })
+            .collect())
+    }
+
+    // MLST where the server has it, otherwise SIZE and MDTM, which only work
+    // for files
+    fn stat(&mut self, path: &str) -> Result<FsEntry, String> {
+        let mlst = with_ftp!(self, ftp => ftp.mlst(Some(path)));
+        if let Some(entry) = mlst.ok().and_then(|line| parse_mlsd_line(&line)) {
+            return Ok(FsEntry {
+                name: file_name(path),
+                path: path.to_string(),
+                size: entry.size,
+                modified: entry.modified,
+                is_dir: entry.is_dir,
+                is_symlink: entry.is_symlink,
+                mode: entry.mode,
+            });
+        }
+        if self.is_dir(path) {
+            return Ok(FsEntry {
+                name: file_name(path),
+                path: path.to_string(),
+                is_dir: true,
+                ..Default::default()
+            });
+        }
+        let size =
+            with_ftp!(self, ftp => ftp.size(path)).map_err(|e| format!("stat error:{}", e))?;
+        let modified = with_ftp!(self, ftp => ftp.mdtm(path))
+            .map(|time| time.and_utc().timestamp().max(0) as u64)
+            .unwrap_or(0);
+        Ok(FsEntry {
+            name: file_name(path),
+            path: path.to_string(),
+            size: size as u64,
+            modified,
+            ..Default::default()
+        })
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<(), String> {
+        self.make_dirs(path).map_err(|e| format!("mkdir error:{}", e))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), String> {
+        with_ftp!(self, ftp => ftp.rename(from, to)).map_err(|e| format!("rename error:{}", e))
+    }
+
+    fn delete(&mut self, path: &str, recursive: bool) -> Result<(), String> {
+        let result = match (self.is_dir(path), recursive) {
+            (false, _) => with_ftp!(self, ftp => ftp.rm(path)),
+            (true, false) => with_ftp!(self, ftp => ftp.rmdir(path)),
+            (true, true) => self.remove_tree(path),
+        };
+        result.map_err(|e| format!("delete error:{}", e))
+    }
+
+    fn read_stream(&mut self, path: &str, sink: &mut dyn Write) -> Result<u64, String> {
+        with_ftp!(self, ftp => ftp.retr(path, |reader| {
+            std::io::copy(reader, sink).map_err(FtpError::ConnectionError)
+        }))
+        .map_err(|e| format!("read error:{}", e))
+    }
+
+    fn write_stream(&mut self, path: &str, mut source: &mut dyn Read) -> Result<u64, String> {
+        with_ftp!(self, ftp => ftp.put_file(path, &mut source))
+            .map_err(|e| format!("write error:{}", e))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FtpTransferProgress {
+    transfer_id: String,
+    direction: String,
+    path: String,
+    local_file: String,
+    transferred: u64,
+    total: u64,
+    // bytes per second since the transfer started
+    speed: u64,
+    status: String,
+    message: String,
+}
+
+struct FtpProgress {
+    app: AppHandle,
+    event: FtpTransferProgress,
+    cancel: Arc<AtomicBool>,
+    started: Instant,
+    last_emit: Instant,
+    // bytes that were already there when resuming, left out of the speed
+    offset: u64,
+}
+
+impl FtpProgress {
+    fn new(
+        app: AppHandle,
+        transfer_id: Option<String>,
+        direction: &str,
+        path: &str,
+        local_file: &str,
+    ) -> Result<FtpProgress, String> {
+        let transfer_id = transfer_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let cancel = Arc::new(AtomicBool::new(false));
+        FTP_TRANSFERS
+            .lock()
+            .map_err(|_| "锁获取失败".to_string())?
+            .insert(transfer_id.clone(), cancel.clone());
+        Ok(FtpProgress {
+            app,
+            event: FtpTransferProgress {
+                transfer_id,
+                direction: direction.to_string(),
+                path: path.to_string(),
+                local_file: local_file.to_string(),
+                transferred: 0,
+                total: 0,
+                speed: 0,
+                status: String::from(STATUS_TRANSFERRING),
+                message: String::new(),
+            },
+            cancel,
+            started: Instant::now(),
+            last_emit: Instant::now(),
+            offset: 0,
+        })
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    fn begin(&mut self, offset: u64, total: u64) {
+        self.offset = offset;
+        self.event.transferred = offset;
+        self.event.total = total;
+        self.started = Instant::now();
+        self.emit();
+    }
+
+    fn advance(&mut self, size: u64) {
+        self.event.transferred += size;
+        if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+            self.emit();
+        }
+    }
+
+    fn emit(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.event.speed = ((self.event.transferred - self.offset) as f64 / elapsed) as u64;
+        }
+        self.last_emit = Instant::now();
+        let _ = self.app.emit(FTP_PROGRESS_EVENT, self.event.clone());
+    }
+
+    fn finish(mut self, result: Result<(), String>) -> Result<(), String> {
+        self.event.status = match &result {
+            Ok(_) => String::from(STATUS_SUCCESS),
+            Err(_) if self.cancelled() => String::from(STATUS_CANCELLED),
+            Err(_) => String::from(STATUS_FAILURE),
+        };
+        if let Err(err) = &result {
+            self.event.message = err.clone();
+        }
+        self.emit();
+        result
+    }
+}
+
+// also covers transfers that never started, e.g. when reconnecting failed,
+// which still get their closing failure event
+impl Drop for FtpProgress {
+    fn drop(&mut self) {
+        if let Ok(mut transfers) = FTP_TRANSFERS.lock() {
+            transfers.remove(&self.event.transfer_id);
+        }
+        if self.event.status == STATUS_TRANSFERRING {
+            self.event.status = String::from(STATUS_FAILURE);
+            self.event.message = String::from("传输未能开始");
+            self.emit();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTls {
+    #[default]
+    None,
+    // AUTH TLS on the normal port
+    Explicit,
+    // TLS from the first byte, usually on port 990
+    Implicit,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpMode {
+    // PASV, switched to EPSV on IPv6 where PASV cannot work
+    #[default]
+    Passive,
+    // EPSV, for servers behind NAT that report a wrong PASV address
+    ExtendedPassive,
+    // PORT, the server connects back to us
+    Active,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTransferType {
+    #[default]
+    Binary,
+    // converts line endings, only useful for text files
+    Ascii,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FtpOptions {
+    #[serde(default)]
+    tls: FtpTls,
+    // PEM file of an extra CA to trust
+    ca_file: Option<String>,
+    // accept self-signed or otherwise unverifiable certificates
+    accept_invalid_certs: Option<bool>,
+    #[serde(default)]
+    mode: FtpMode,
+    // seconds, for the control and data connections
+    connect_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+    #[serde(default)]
+    transfer_type: FtpTransferType,
+    // directory to change into after login
+    cwd: Option<String>,
+}
+
+fn tls_connector(options: &FtpOptions) -> Result<NativeTlsConnector, String> {
+    let mut builder = TlsConnector::builder();
+    if let Some(ca_file) = &options.ca_file {
+        let pem = std::fs::read(ca_file).map_err(|e| format!("读取CA证书失败: {}", e))?;
+        let cert = Certificate::from_pem(&pem).map_err(|e| format!("解析CA证书失败: {}", e))?;
+        builder.add_root_certificate(cert);
+    }
+    if options.accept_invalid_certs.unwrap_or(false) {
+        builder.danger_accept_invalid_certs(true);
+    }
+    let connector = builder
+        .build()
+        .map_err(|e| format!("初始化TLS失败: {}", e))?;
+    Ok(NativeTlsConnector::from(connector))
+}
+
+// accepts names, IPv4 and IPv6 addresses, the latter with or without brackets
+fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("解析主机名失败: {}", e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("解析主机名失败: {}", host));
+    }
+    Ok(addrs)
+}
+
+fn open_tcp(addrs: &[SocketAddr], options: &FtpOptions) -> Result<TcpStream, String> {
+    let timeout = Duration::from_secs(options.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT));
+    let mut last_error = String::new();
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, timeout) {
+            Ok(stream) => {
+                // bounds the greeting and TLS handshake, configure sets the
+                // read timeout for the rest of the session
+                let _ = stream.set_read_timeout(Some(timeout));
+                return Ok(stream);
+            }
+            Err(err) => last_error = format!("{}: {}", addr, err),
+        }
+    }
+    Err(format!("连接失败: {}", last_error))
+}
+
+fn open_client(
     host: &str,
+    addrs: &[SocketAddr],
+    options: &FtpOptions,
+) -> Result<FtpClient, String> {
+    let domain = host.trim_start_matches('[').trim_end_matches(']');
+    match options.tls {
+        FtpTls::None => FtpStream::connect_with_stream(open_tcp(addrs, options)?)
+            .map(|ftp| FtpClient::Plain(ftp, None))
+            .map_err(|e| format!("连接失败: {}", e)),
+        FtpTls::Explicit => {
+            let ftp = NativeTlsFtpStream::connect_with_stream(open_tcp(addrs, options)?)
+                .map_err(|e| format!("连接失败: {}", e))?;
+            ftp.into_secure(tls_connector(options)?, domain)
+                .map(|ftp| FtpClient::Secure(ftp, None))
+                .map_err(|e| format!("TLS握手失败: {}", e))
+        }
+        // suppaftp opens this connection itself, without a timeout
+        FtpTls::Implicit => {
+            NativeTlsFtpStream::connect_secure_implicit(addrs, tls_connector(options)?, domain)
+                .map(|ftp| FtpClient::Secure(ftp, None))
+                .map_err(|e| format!("TLS连接失败: {}", e))
+        }
+    }
+}
+
+// applies mode and timeouts to the control connection and to every data
+// connection opened later
+fn configure(client: FtpClient, options: &FtpOptions) -> Result<FtpClient, String> {
+    let connect_timeout =
+        Duration::from_secs(options.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT));
+    let read_timeout = options.read_timeout.filter(|secs| *secs > 0).map(Duration::from_secs);
+    let ipv6 = with_ftp!(&client, ftp => ftp.get_ref().peer_addr())
+        .map(|addr| addr.is_ipv6())
+        .unwrap_or(false);
+    with_ftp!(&client, ftp => {
+        let _ = ftp.get_ref().set_read_timeout(read_timeout);
+        let _ = ftp.get_ref().set_write_timeout(read_timeout);
+    });
+    let data_stream = move |addr: SocketAddr| {
+        let stream = TcpStream::connect_timeout(&addr, connect_timeout)
+            .map_err(FtpError::ConnectionError)?;
+        let _ = stream.set_read_timeout(read_timeout);
+        let _ = stream.set_write_timeout(read_timeout);
+        Ok(stream)
+    };
+    let mut client = map_ftp!(client, ftp => ftp.passive_stream_builder(data_stream));
+    match options.mode {
+        FtpMode::Passive if !ipv6 => with_ftp!(&mut client, ftp => ftp.set_mode(Mode::Passive)),
+        FtpMode::Passive | FtpMode::ExtendedPassive => {
+            with_ftp!(&mut client, ftp => ftp.set_mode(Mode::ExtendedPassive))
+        }
+        // suppaftp only sends PORT, which has no room for IPv6 addresses
+        FtpMode::Active if ipv6 => return Err("主动模式不支持IPv6连接".to_string()),
+        FtpMode::Active => {
+            let accept_timeout = read_timeout.unwrap_or(connect_timeout);
+            client = map_ftp!(client, ftp => ftp.active_mode(accept_timeout));
+        }
+    }
+    Ok(client)
+}
+
+#[derive(Debug, Clone)]
+struct FtpConnectConfig {
+    host: String,
     port: u16,
-    username: &str,
-    password: &str,
-) -> Result<String, String> {
-    let url = format!("{}:{}", host, port);
-    let addr: SocketAddr = url.parse().expect("invalid hostname");
-    let mut ftp = FtpStream::connect_timeout(addr, Duration::from_secs(10))
-        .map_err(|e| format!("连接失败: {}", e))?;
+    username: String,
+    password: String,
+    options: FtpOptions,
+}
+
+struct FtpConnectionMeta {
+    connected_at: u64,
+    last_used: u64,
+    last_noop: u64,
+    alive: bool,
+    reconnect_count: u32,
+    message: String,
+}
 
-    ftp.login(username, password)
+// each connection has its own lock, so a long transfer only blocks commands
+// on the same connection; `client` is None after the link was lost
+struct FtpConnection {
+    config: FtpConnectConfig,
+    client: Mutex<Option<FtpClient>>,
+    meta: Mutex<FtpConnectionMeta>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FtpConnectionInfo {
+    key: String,
+    host: String,
+    port: u16,
+    username: String,
+    tls: FtpTls,
+    connected_at: u64,
+    last_used: u64,
+    alive: bool,
+    // a command or transfer currently holds the connection
+    busy: bool,
+    reconnect_count: u32,
+    message: String,
+}
+
+static KEEPALIVE_WORKER: Once = Once::new();
+
+// also used on reconnect, so the session ends up in the same state
+fn login(config: &FtpConnectConfig) -> Result<FtpClient, String> {
+    let options = &config.options;
+    let addrs = resolve(&config.host, config.port)?;
+    let ftp = open_client(&config.host, &addrs, options)?;
+    let mut ftp = configure(ftp, options)?;
+    with_ftp!(&mut ftp, ftp => ftp.login(&config.username, &config.password))
         .map_err(|e| format!("认证失败: {}", e))?;
+    let file_type = match options.transfer_type {
+        FtpTransferType::Binary => FileType::Binary,
+        FtpTransferType::Ascii => FileType::Ascii(FormatControl::Default),
+    };
+    with_ftp!(&mut ftp, ftp => ftp.transfer_type(file_type))
+        .map_err(|e| format!("设置传输类型失败: {}", e))?;
+    if let Some(cwd) = options.cwd.as_deref().filter(|cwd| !cwd.is_empty()) {
+        with_ftp!(&mut ftp, ftp => ftp.cwd(cwd)).map_err(|e| format!("切换目录失败: {}", e))?;
+    }
+    Ok(ftp)
+}
+
+impl FtpConnection {
+    fn update<F: FnOnce(&mut FtpConnectionMeta)>(&self, f: F) {
+        if let Ok(mut meta) = self.meta.lock() {
+            f(&mut meta);
+        }
+    }
 
+    fn info(&self, key: &str) -> FtpConnectionInfo {
+        let busy = self.client.try_lock().is_err();
+        let meta = self.meta.lock().unwrap_or_else(|e| e.into_inner());
+        FtpConnectionInfo {
+            key: key.to_string(),
+            host: self.config.host.clone(),
+            port: self.config.port,
+            username: self.config.username.clone(),
+            tls: self.config.options.tls,
+            connected_at: meta.connected_at,
+            last_used: meta.last_used,
+            alive: meta.alive,
+            busy,
+            reconnect_count: meta.reconnect_count,
+            message: meta.message.clone(),
+        }
+    }
+
+    fn reconnect(&self, client: &mut Option<FtpClient>) -> Result<(), String> {
+        match login(&self.config) {
+            Ok(ftp) => {
+                *client = Some(ftp);
+                let now = timestamp();
+                self.update(|meta| {
+                    meta.connected_at = now;
+                    meta.last_noop = now;
+                    meta.alive = true;
+                    meta.reconnect_count += 1;
+                    meta.message = String::new();
+                });
+                Ok(())
+            }
+            Err(err) => {
+                self.update(|meta| meta.message = err.clone());
+                Err(err)
+            }
+        }
+    }
+
+    fn drop_client(&self, client: &mut Option<FtpClient>, message: String) {
+        *client = None;
+        self.update(|meta| {
+            meta.alive = false;
+            meta.message = message;
+        });
+    }
+
+    // a failed command is not retried, since it may have been half done;
+    // if the link turns out to be gone the next command reconnects first
+    fn run<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut FtpClient) -> Result<T, String>,
+    {
+        let mut client = self.client.lock().map_err(|_| "锁获取失败".to_string())?;
+        if client.is_none() {
+            self.reconnect(&mut client)?;
+        }
+        self.update(|meta| meta.last_used = timestamp());
+        let Some(ftp) = client.as_mut() else {
+            return Err("FTP连接已断开".to_string());
+        };
+        let result = f(ftp);
+        if result.is_err() {
+            if let Err(err) = with_ftp!(ftp, ftp => ftp.noop()) {
+                self.drop_client(&mut client, format!("连接已断开: {}", err));
+            }
+        }
+        result
+    }
+
+    // skips connections that are in use, their traffic keeps them open
+    fn keepalive(&self, now: u64) {
+        let idle = self
+            .meta
+            .lock()
+            .map(|meta| now.saturating_sub(meta.last_used.max(meta.last_noop)))
+            .is_ok_and(|silence| silence >= KEEPALIVE_INTERVAL);
+        if !idle {
+            return;
+        }
+        let Ok(mut client) = self.client.try_lock() else {
+            return;
+        };
+        self.update(|meta| meta.last_noop = now);
+        let result = match client.as_mut() {
+            Some(ftp) => with_ftp!(ftp, ftp => ftp.noop())
+                .map_err(|e| format!("连接已断开: {}", e)),
+            None => Err(String::new()),
+        };
+        if let Err(err) = result {
+            if client.is_some() {
+                self.drop_client(&mut client, err);
+            }
+            let _ = self.reconnect(&mut client);
+        }
+    }
+}
+
+fn run_keepalive() {
+    loop {
+        thread::sleep(KEEPALIVE_TICK);
+        let connections: Vec<Arc<FtpConnection>> = match FTP_CLIENTS.lock() {
+            Ok(list) => list.values().cloned().collect(),
+            Err(_) => continue,
+        };
+        let now = timestamp();
+        for connection in connections {
+            connection.keepalive(now);
+        }
+    }
+}
+
+fn get_connection(key: &str) -> Result<Arc<FtpConnection>, String> {
+    FTP_CLIENTS
+        .lock()
+        .map_err(|_| "锁获取失败".to_string())?
+        .get(key)
+        .cloned()
+        .ok_or_else(|| "指定的FTP连接不存在".to_string())
+}
+
+// runs `f` with the connection locked, on the calling thread
+pub fn with_ftp_client<T, F>(key: &str, f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut FtpClient) -> Result<T, String>,
+{
+    get_connection(key)?.run(f)
+}
+
+// runs `f` on a blocking thread with the connection locked, so FTP I/O never
+// stalls the async runtime
+async fn run_ftp<T, F>(key: &str, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut FtpClient) -> Result<T, String> + Send + 'static,
+{
+    let connection = get_connection(key)?;
+    tokio::task::spawn_blocking(move || connection.run(f))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn connect_ftp(
+    host: String,
+    port: u16,
+    username: String,
+    password: String,
+    options: Option<FtpOptions>,
+) -> Result<String, String> {
+    let config = FtpConnectConfig {
+        host,
+        port,
+        username,
+        password,
+        options: options.unwrap_or_default(),
+    };
+    let (ftp, config) = tokio::task::spawn_blocking(move || login(&config).map(|ftp| (ftp, config)))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let now = timestamp();
+    let connection = FtpConnection {
+        config,
+        client: Mutex::new(Some(ftp)),
+        meta: Mutex::new(FtpConnectionMeta {
+            connected_at: now,
+            last_used: now,
+            last_noop: now,
+            alive: true,
+            reconnect_count: 0,
+            message: String::new(),
+        }),
+    };
     let key = Uuid::new_v4().to_string();
     FTP_CLIENTS
         .lock()
         .map_err(|_| "锁获取失败".to_string())?
-        .insert(key.clone(), ftp);
+        .insert(key.clone(), Arc::new(connection));
+    KEEPALIVE_WORKER.call_once(|| {
+        thread::spawn(run_keepalive);
+    });
     Ok(key)
 }
 
+// the connection is forgotten right away; QUIT is sent once a running
+// command on it has finished
 #[tauri::command]
-pub async fn disconnect_ftp(key: &str) -> Result<(), String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-       let ftp = client
-        .get_mut(key)
+pub async fn disconnect_ftp(key: String) -> Result<(), String> {
+    let connection = FTP_CLIENTS
+        .lock()
+        .map_err(|_| "锁获取失败".to_string())?
+        .remove(&key)
         .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
-    ftp.quit().map_err(|e| format!("断开连接失败: {}", e))?;
-    client.remove(key);
-    Ok(())
+    tokio::task::spawn_blocking(move || {
+        let mut client = connection.client.lock().map_err(|_| "锁获取失败".to_string())?;
+        match client.take() {
+            Some(mut ftp) => with_ftp!(&mut ftp, ftp => ftp.quit())
+                .map_err(|e| format!("断开连接失败: {}", e)),
+            None => Ok(()),
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-pub async fn ftp_list(key: &str, path: &str) -> Result<Vec<String>, String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-    let ftp = client
-        .get_mut(key)
-        .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
+pub async fn list_ftp_connections() -> Result<Vec<FtpConnectionInfo>, String> {
+    let list = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
+    Ok(list
+        .iter()
+        .map(|(key, connection)| connection.info(key))
+        .collect())
+}
 
-    let files = ftp
-        .list(Some(&format!("{}", path)))
-        .map_err(|e| format!("获取文件列表失败: {}", e))?;
-    Ok(files)
+#[tauri::command]
+pub async fn ftp_list(key: String, path: String) -> Result<Vec<FtpEntry>, String> {
+    run_ftp(&key, move |ftp| {
+        ftp.list_entries(&path)
+            .map_err(|e| format!("获取文件列表失败: {}", e))
+    })
+    .await
 }
 
+// streams from disk in chunks, reporting "ftp_transfer_progress" events
+// under `transfer_id`; with `resume` an existing remote part is continued
+// via APPE
+#[tauri::command]
+pub async fn ftp_upload_file(
+    app: AppHandle,
+    key: String,
+    path: String,
+    local_file: String,
+    transfer_id: Option<String>,
+    resume: Option<bool>,
+) -> Result<(), String> {
+    let mut progress = FtpProgress::new(app, transfer_id, DIRECTION_UPLOAD, &path, &local_file)?;
+    let resume = resume.unwrap_or(false);
+    run_ftp(&key, move |ftp| {
+        let result = ftp.upload_stream(Path::new(&local_file), &path, resume, &mut progress);
+        progress.finish(result)
+    })
+    .await
+}
 
+// streams to disk in chunks; with `resume` a partial local file is continued
+// from its current size via REST
 #[tauri::command]
-pub async fn ftp_upload_file(key: &str, path: &str, local_file: &str) -> Result<(), String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-    let ftp = client
-        .get_mut(key)
-        .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
-    let mut reader = std::fs::File::open(local_file).map_err(|e| format!("打开文件失败: {}", e))?;
-    ftp.put_file(&path, &mut reader)
-        .map_err(|e| format!("上传文件失败: {}", e))?;
-    Ok(())
+pub async fn ftp_download_file(
+    app: AppHandle,
+    key: String,
+    path: String,
+    local_file: String,
+    transfer_id: Option<String>,
+    resume: Option<bool>,
+) -> Result<(), String> {
+    let mut progress = FtpProgress::new(app, transfer_id, DIRECTION_DOWNLOAD, &path, &local_file)?;
+    let resume = resume.unwrap_or(false);
+    run_ftp(&key, move |ftp| {
+        let result = ftp.download_stream(&path, Path::new(&local_file), resume, &mut progress);
+        progress.finish(result)
+    })
+    .await
 }
 
 #[tauri::command]
-pub async fn ftp_download_file(key: &str, path: &str, local_file: &str) -> Result<(), String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-    let ftp = client
-        .get_mut(key)
-        .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
-     let mut buf = ftp
-        .retr_as_buffer(&path).map_err(|e| format!("下载文件失败: {}", e))?;
-    let mut data_buf = Vec::new();
-    let _ = buf.read_to_end(&mut data_buf);
-
-    if std::path::Path::new(local_file).exists() {
-        let mut f = std::fs::File::open(local_file).map_err(|e| format!("打开文件失败: {}", e))?;
-        f.write_all(&data_buf)
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-    } else {
-        let mut f =
-            std::fs::File::create(local_file).map_err(|e| format!("创建文件失败: {}", e))?;
-        f.write_all(&data_buf)
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-    }
-    /* 
-   
-    
-    let mut file = std::fs::File::create(local_file).map_err(|e| format!("创建文件失败: {}", e))?;
-    file.write_all(&data_buf)
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-        */
+pub async fn cancel_ftp_transfer(transfer_id: &str) -> Result<(), String> {
+    let transfers = FTP_TRANSFERS.lock().map_err(|_| "锁获取失败".to_string())?;
+    let cancel = transfers
+        .get(transfer_id)
+        .ok_or_else(|| "指定的传输不存在".to_string())?;
+    cancel.store(true, Ordering::SeqCst);
     Ok(())
 }
 
 #[tauri::command]
-pub async fn ftp_delete_file(key: &str, path: &str) -> Result<(), String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-    let ftp = client
-        .get_mut(key)
-        .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
-    ftp.rm(&format!("{}", path))
-        .map_err(|e| format!("删除文件失败: {}", e))?;
-    Ok(())
+pub async fn ftp_delete_file(key: String, path: String) -> Result<(), String> {
+    run_ftp(&key, move |ftp| {
+        with_ftp!(ftp, ftp => ftp.rm(&path))
+            .map_err(|e| format!("删除文件失败: {}", e))
+    })
+    .await
 }
 
 #[tauri::command]
-pub async fn ftp_delete_dir(key: &str, path: &str) -> Result<(), String> {
-    let mut client = FTP_CLIENTS.lock().map_err(|_| "锁获取失败".to_string())?;
-    let ftp = client
-        .get_mut(key)
-        .ok_or_else(|| "指定的FTP连接不存在".to_string())?;
-    ftp.rmdir(&format!("{}", path))
-        .map_err(|e| format!("删除目录失败: {}", e))?;
-    Ok(())
+pub async fn ftp_delete_dir(
+    key: String,
+    path: String,
+    recursive: Option<bool>,
+) -> Result<(), String> {
+    run_ftp(&key, move |ftp| {
+        if recursive.unwrap_or(false) {
+            return ftp
+                .remove_tree(&path)
+                .map_err(|e| format!("删除目录失败: {}", e));
+        }
+        with_ftp!(ftp, ftp => ftp.rmdir(&path))
+            .map_err(|e| format!("删除目录失败: {}", e))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn ftp_mkdir(key: String, path: String, recursive: Option<bool>) -> Result<(), String> {
+    run_ftp(&key, move |ftp| {
+        if recursive.unwrap_or(false) {
+            return ftp
+                .make_dirs(&path)
+                .map_err(|e| format!("创建目录失败: {}", e));
+        }
+        with_ftp!(ftp, ftp => ftp.mkdir(&path))
+            .map_err(|e| format!("创建目录失败: {}", e))
+    })
+    .await
+}
+
+// also moves files between directories on the same server
+#[tauri::command]
+pub async fn ftp_rename(key: String, from: String, to: String) -> Result<(), String> {
+    run_ftp(&key, move |ftp| {
+        with_ftp!(ftp, ftp => ftp.rename(&from, &to))
+            .map_err(|e| format!("重命名失败: {}", e))
+    })
+    .await
+}
+
+// returns the number of files uploaded
+#[tauri::command]
+pub async fn ftp_upload_dir(key: String, path: String, local_dir: String) -> Result<u64, String> {
+    run_ftp(&key, move |ftp| ftp.upload_tree(Path::new(&local_dir), &path)).await
+}
+
+// returns the number of files downloaded
+#[tauri::command]
+pub async fn ftp_download_dir(key: String, path: String, local_dir: String) -> Result<u64, String> {
+    run_ftp(&key, move |ftp| ftp.download_tree(&path, Path::new(&local_dir))).await
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FtpFileInfo {
+    path: String,
+    size: u64,
+    // unix seconds from MDTM, None when the server does not support it
+    modified: Option<i64>,
+}
+
+#[tauri::command]
+pub async fn ftp_file_info(key: String, path: String) -> Result<FtpFileInfo, String> {
+    run_ftp(&key, move |ftp| {
+        let size = with_ftp!(ftp, ftp => ftp.size(&path))
+            .map_err(|e| format!("获取文件大小失败: {}", e))?;
+        let modified = with_ftp!(ftp, ftp => ftp.mdtm(&path))
+            .ok()
+            .map(|time| time.and_utc().timestamp());
+        Ok(FtpFileInfo {
+            path,
+            size: size as u64,
+            modified,
+        })
+    })
+    .await
 }
@@ -0,0 +1,984 @@
+use super::ssh_transfer::{DIRECTION_DOWNLOAD, DIRECTION_UPLOAD};
+use super::tree::local_mode;
+use crate::toolbox::ftp_list::{
+    format_list_line, format_mlsd_line, format_mlsx_time, permission_string, FtpEntry,
+};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+use tokio::fs;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+const FTP_SERVER_EVENT: &str = "ftp_server_status";
+const DEFAULT_PORT: u16 = 2121;
+const DATA_TIMEOUT: Duration = Duration::from_secs(30);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+const MAX_LINE_LENGTH: u64 = 8192;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const BUFFER_SIZE: usize = 64 * 1024;
+const FEATURES: &str = concat!(
+    "211-Features:\r\n",
+    " EPSV\r\n MDTM\r\n MLST type*;size*;modify*;unix.mode*;\r\n",
+    " PASV\r\n REST STREAM\r\n SIZE\r\n UTF8\r\n",
+    "211 End\r\n"
+);
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FtpServerOptions {
+    port: Option<u16>,
+    // anonymous access when empty, any user name and password is accepted
+    username: Option<String>,
+    password: Option<String>,
+    read_only: Option<bool>,
+    // inclusive range for passive data connections, any free port when unset
+    passive_port_start: Option<u16>,
+    passive_port_end: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FtpServerInfo {
+    root: String,
+    port: u16,
+    anonymous: bool,
+    read_only: bool,
+    passive_ports: Option<(u16, u16)>,
+    started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FtpServerTransfer {
+    direction: String,
+    path: String,
+    transferred: u64,
+    // 0 for uploads, the size is not known up front
+    total: u64,
+    started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FtpServerClient {
+    client_id: String,
+    address: String,
+    username: Option<String>,
+    connected_at: u64,
+    cwd: String,
+    transfer: Option<FtpServerTransfer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FtpServerStatus {
+    running: bool,
+    server: Option<FtpServerInfo>,
+    clients: Vec<FtpServerClient>,
+}
+
+struct FtpServer {
+    server_id: String,
+    info: FtpServerInfo,
+    clients: HashMap<String, FtpServerClient>,
+    // dropping it ends the accept loop and every client session
+    _stop: watch::Sender<()>,
+}
+
+struct ServerConfig {
+    server_id: String,
+    root: PathBuf,
+    credentials: Option<(String, String)>,
+    read_only: bool,
+    passive_ports: Option<(u16, u16)>,
+}
+
+lazy_static! {
+    static ref FTP_SERVER: Arc<Mutex<Option<FtpServer>>> = Arc::new(Mutex::new(None));
+}
+
+fn server_status() -> FtpServerStatus {
+    let server = FTP_SERVER.lock().ok();
+    match server.as_ref().and_then(|server| server.as_ref()) {
+        Some(server) => {
+            let mut clients: Vec<FtpServerClient> = server.clients.values().cloned().collect();
+            clients.sort_by_key(|client| client.connected_at);
+            FtpServerStatus {
+                running: true,
+                server: Some(server.info.clone()),
+                clients,
+            }
+        }
+        None => FtpServerStatus {
+            running: false,
+            server: None,
+            clients: Vec::new(),
+        },
+    }
+}
+
+fn notify(app: &AppHandle) {
+    let _ = app.emit(FTP_SERVER_EVENT, server_status());
+}
+
+// runs `f` on the client, unless its server was stopped in the meantime
+fn update_client<F: FnOnce(&mut HashMap<String, FtpServerClient>)>(server_id: &str, f: F) {
+    if let Ok(mut server) = FTP_SERVER.lock() {
+        if let Some(server) = server
+            .as_mut()
+            .filter(|server| server.server_id == server_id)
+        {
+            f(&mut server.clients);
+        }
+    }
+}
+
+// virtual paths are absolute with `/` being the shared directory, and `..`
+// stops there
+fn resolve_path(cwd: &str, arg: &str) -> String {
+    let mut parts: Vec<&str> = match arg.starts_with('/') {
+        true => Vec::new(),
+        false => cwd.split('/').filter(|part| !part.is_empty()).collect(),
+    };
+    for part in arg.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+// strips the `-la` style flags some clients pass to LIST
+fn list_argument(arg: &str) -> &str {
+    let mut arg = arg.trim();
+    while arg.starts_with('-') {
+        arg = arg
+            .split_once(' ')
+            .map(|(_, rest)| rest.trim_start())
+            .unwrap_or("");
+    }
+    arg
+}
+
+fn quote_path(path: &str) -> String {
+    format!("\"{}\"", path.replace('"', "\"\""))
+}
+
+fn local_entry(name: String, metadata: &std::fs::Metadata) -> FtpEntry {
+    let mode = local_mode(metadata);
+    FtpEntry {
+        name,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        modified: metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        is_dir: metadata.is_dir(),
+        mode: Some(mode),
+        permissions: Some(permission_string(mode)),
+        ..Default::default()
+    }
+}
+
+// `h1,h2,h3,h4,p1,p2` from PORT
+fn parse_port(arg: &str) -> Option<SocketAddr> {
+    let numbers: Vec<u8> = arg
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let [h1, h2, h3, h4, p1, p2] = numbers[..] else {
+        return None;
+    };
+    let ip = IpAddr::from([h1, h2, h3, h4]);
+    Some(SocketAddr::new(ip, u16::from(p1) << 8 | u16::from(p2)))
+}
+
+// `|1|ip|port|` from EPRT, the delimiter being the first character
+fn parse_eprt(arg: &str) -> Option<SocketAddr> {
+    let delimiter = arg.chars().next()?;
+    let parts: Vec<&str> = arg.split(delimiter).collect();
+    let [_, _, ip, port, _] = parts[..] else {
+        return None;
+    };
+    Some(SocketAddr::new(ip.parse().ok()?, port.parse().ok()?))
+}
+
+enum DataChannel {
+    Passive(TcpListener),
+    Active(SocketAddr),
+}
+
+struct Session {
+    app: AppHandle,
+    config: Arc<ServerConfig>,
+    client_id: String,
+    writer: OwnedWriteHalf,
+    local_ip: IpAddr,
+    peer: SocketAddr,
+    username: Option<String>,
+    logged_in: bool,
+    cwd: String,
+    data: Option<DataChannel>,
+    rest: u64,
+    rename_from: Option<String>,
+}
+
+impl Session {
+    async fn reply(&mut self, code: u16, text: &str) -> io::Result<()> {
+        self.send(&format!("{} {}\r\n", code, text)).await
+    }
+
+    async fn send(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes()).await
+    }
+
+    fn update<F: FnOnce(&mut FtpServerClient)>(&self, f: F) {
+        update_client(&self.config.server_id, |clients| {
+            if let Some(client) = clients.get_mut(&self.client_id) {
+                f(client);
+            }
+        });
+    }
+
+    // links inside the shared directory may point anywhere, so the place a
+    // path resolves to has to stay below the root; paths that do not exist
+    // yet are checked through their parent. A dangling link is refused, as
+    // creating the file would follow it to wherever it points.
+    async fn check_inside(&self, real: &Path) -> io::Result<()> {
+        let resolved = match fs::canonicalize(real).await {
+            Ok(resolved) => resolved,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if fs::symlink_metadata(real).await.is_ok() {
+                    return Err(io::Error::new(
+                        ErrorKind::PermissionDenied,
+                        "Link target does not exist",
+                    ));
+                }
+                match real.parent() {
+                    Some(parent) => fs::canonicalize(parent).await?,
+                    None => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        if !resolved.starts_with(&self.config.root) {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "Path is outside the shared directory",
+            ));
+        }
+        Ok(())
+    }
+
+    async fn real_path(&self, path: &str) -> io::Result<PathBuf> {
+        let mut real = self.config.root.clone();
+        for part in path.split('/') {
+            // drive prefixes and the like would replace the root on windows
+            if let Some(Component::Normal(_)) = Path::new(part).components().next() {
+                real.push(part);
+            }
+        }
+        self.check_inside(&real).await?;
+        Ok(real)
+    }
+
+    async fn run(mut self, reader: OwnedReadHalf) -> io::Result<()> {
+        self.reply(220, "rust_box FTP server ready").await?;
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let mut limited = (&mut reader).take(MAX_LINE_LENGTH);
+            let size = match timeout(IDLE_TIMEOUT, limited.read_until(b'\n', &mut line)).await {
+                Ok(size) => size?,
+                Err(_) => return self.reply(421, "Idle timeout, closing connection").await,
+            };
+            if size == 0 {
+                return Ok(());
+            }
+            if size as u64 == MAX_LINE_LENGTH && !line.ends_with(b"\n") {
+                return self.reply(500, "Command line too long").await;
+            }
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\r', '\n']);
+            let (command, arg) = text.split_once(' ').unwrap_or((text, ""));
+            let command = command.to_uppercase();
+            if command == "QUIT" {
+                return self.reply(221, "Goodbye").await;
+            }
+            self.handle(&command, arg).await?;
+        }
+    }
+
+    // only failures on the control connection end the session, everything
+    // else is answered with an error code
+    async fn handle(&mut self, command: &str, arg: &str) -> io::Result<()> {
+        // both only apply to the command that directly follows
+        let rest = std::mem::take(&mut self.rest);
+        let rename_from = self.rename_from.take();
+
+        match command {
+            "USER" => return self.user(arg).await,
+            "PASS" => return self.pass(arg).await,
+            "SYST" => return self.reply(215, "UNIX Type: L8").await,
+            "FEAT" => return self.send(FEATURES).await,
+            "OPTS" => return self.reply(200, "OK").await,
+            "NOOP" => return self.reply(200, "OK").await,
+            "AUTH" => return self.reply(502, "TLS is not supported").await,
+            _ if !self.logged_in => {
+                return self.reply(530, "Please login with USER and PASS").await
+            }
+            _ => {}
+        }
+
+        match command {
+            "PWD" | "XPWD" => {
+                let text = format!("{} is the current directory", quote_path(&self.cwd));
+                self.reply(257, &text).await
+            }
+            "CWD" | "XCWD" => self.change_dir(arg).await,
+            "CDUP" | "XCUP" => self.change_dir("..").await,
+            "TYPE" => match arg.to_uppercase().as_str() {
+                // files are always sent as they are, ASCII included
+                "I" | "A" | "L 8" | "A N" => self.reply(200, "Type set").await,
+                _ => self.reply(504, "Type not supported").await,
+            },
+            "MODE" | "STRU" => match arg.to_uppercase().as_str() {
+                "S" | "F" => self.reply(200, "OK").await,
+                _ => self.reply(504, "Not supported").await,
+            },
+            "PASV" => self.passive(false).await,
+            "EPSV" => self.passive(true).await,
+            "PORT" => self.active(parse_port(arg)).await,
+            "EPRT" => self.active(parse_eprt(arg)).await,
+            "REST" => match arg.trim().parse() {
+                Ok(offset) => {
+                    self.rest = offset;
+                    self.reply(350, &format!("Restarting at {}", offset)).await
+                }
+                Err(_) => self.reply(501, "Invalid offset").await,
+            },
+            "ABOR" => {
+                self.data = None;
+                self.reply(226, "No transfer to abort").await
+            }
+            "LIST" | "NLST" | "MLSD" => self.list(command, list_argument(arg)).await,
+            "MLST" => self.mlst(arg).await,
+            "SIZE" | "MDTM" => self.file_fact(command, arg).await,
+            "RETR" => self.retrieve(arg, rest).await,
+            "STOR" | "APPE" | "DELE" | "MKD" | "XMKD" | "RMD" | "XRMD" | "RNFR" | "RNTO"
+                if self.config.read_only =>
+            {
+                self.reply(550, "Permission denied, the server is read only")
+                    .await
+            }
+            "STOR" => self.store(arg, rest, false).await,
+            "APPE" => self.store(arg, 0, true).await,
+            "DELE" => {
+                let path = resolve_path(&self.cwd, arg);
+                let result = async { fs::remove_file(self.real_path(&path).await?).await }.await;
+                self.done(result.map(|_| (250, String::from("File deleted"))))
+                    .await
+            }
+            "MKD" | "XMKD" => {
+                let path = resolve_path(&self.cwd, arg);
+                let result = async { fs::create_dir(self.real_path(&path).await?).await }.await;
+                let text = format!("{} created", quote_path(&path));
+                self.done(result.map(|_| (257, text))).await
+            }
+            "RMD" | "XRMD" => {
+                let path = resolve_path(&self.cwd, arg);
+                let result = async { fs::remove_dir(self.real_path(&path).await?).await }.await;
+                self.done(result.map(|_| (250, String::from("Directory removed"))))
+                    .await
+            }
+            "RNFR" => {
+                let path = resolve_path(&self.cwd, arg);
+                let result = async { fs::symlink_metadata(self.real_path(&path).await?).await };
+                match result.await {
+                    Ok(_) => {
+                        self.rename_from = Some(path);
+                        self.reply(350, "Ready for RNTO").await
+                    }
+                    Err(err) => self.reply(550, &err.to_string()).await,
+                }
+            }
+            "RNTO" => {
+                let Some(from) = rename_from else {
+                    return self.reply(503, "Send RNFR first").await;
+                };
+                let to = resolve_path(&self.cwd, arg);
+                let result = async {
+                    let (from, to) = (self.real_path(&from).await?, self.real_path(&to).await?);
+                    fs::rename(from, to).await
+                };
+                let result = result.await;
+                self.done(result.map(|_| (250, String::from("Renamed"))))
+                    .await
+            }
+            _ => self.reply(502, "Command not implemented").await,
+        }
+    }
+
+    async fn done(&mut self, result: io::Result<(u16, String)>) -> io::Result<()> {
+        match result {
+            Ok((code, text)) => self.reply(code, &text).await,
+            Err(err) => self.reply(550, &err.to_string()).await,
+        }
+    }
+
+    async fn user(&mut self, arg: &str) -> io::Result<()> {
+        self.username = Some(arg.to_string());
+        self.logged_in = false;
+        match self.config.credentials {
+            None => {
+                self.reply(331, "Anonymous login, any password will do")
+                    .await
+            }
+            Some(_) => self.reply(331, "Password required").await,
+        }
+    }
+
+    async fn pass(&mut self, arg: &str) -> io::Result<()> {
+        let Some(username) = self.username.clone() else {
+            return self.reply(503, "Send USER first").await;
+        };
+        let accepted = match &self.config.credentials {
+            None => true,
+            Some((user, password)) => *user == username && password == arg,
+        };
+        if !accepted {
+            return self.reply(530, "Login incorrect").await;
+        }
+        self.logged_in = true;
+        self.update(|client| client.username = Some(username));
+        notify(&self.app);
+        self.reply(230, "Login successful").await
+    }
+
+    async fn change_dir(&mut self, arg: &str) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        match async { fs::metadata(self.real_path(&path).await?).await }.await {
+            Ok(metadata) if metadata.is_dir() => {
+                self.cwd = path.clone();
+                self.update(|client| client.cwd = path);
+                self.reply(250, "Directory changed").await
+            }
+            Ok(_) => self.reply(550, "Not a directory").await,
+            Err(err) => self.reply(550, &err.to_string()).await,
+        }
+    }
+
+    async fn bind_passive(&self) -> io::Result<TcpListener> {
+        let Some((start, end)) = self.config.passive_ports else {
+            return TcpListener::bind((self.local_ip, 0)).await;
+        };
+        for port in start..=end {
+            if let Ok(listener) = TcpListener::bind((self.local_ip, port)).await {
+                return Ok(listener);
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::AddrInUse,
+            "no free port in the passive range",
+        ))
+    }
+
+    async fn passive(&mut self, extended: bool) -> io::Result<()> {
+        let listener = match self.bind_passive().await {
+            Ok(listener) => listener,
+            Err(err) => return self.reply(425, &err.to_string()).await,
+        };
+        let port = listener.local_addr()?.port();
+        self.data = Some(DataChannel::Passive(listener));
+        match (extended, self.local_ip) {
+            (true, _) => {
+                let text = format!("Entering Extended Passive Mode (|||{}|)", port);
+                self.reply(229, &text).await
+            }
+            (false, IpAddr::V4(ip)) => {
+                let [h1, h2, h3, h4] = ip.octets();
+                let text = format!(
+                    "Entering Passive Mode ({},{},{},{},{},{})",
+                    h1,
+                    h2,
+                    h3,
+                    h4,
+                    port >> 8,
+                    port & 0xff
+                );
+                self.reply(227, &text).await
+            }
+            (false, IpAddr::V6(_)) => {
+                self.data = None;
+                self.reply(522, "Use EPSV for IPv6").await
+            }
+        }
+    }
+
+    // the data connection may only go back to the client itself, which
+    // rules out FTP bounce attacks
+    async fn active(&mut self, address: Option<SocketAddr>) -> io::Result<()> {
+        match address {
+            Some(address) if address.ip() == self.peer.ip() => {
+                self.data = Some(DataChannel::Active(address));
+                self.reply(200, "Active mode set").await
+            }
+            Some(_) => {
+                self.reply(504, "Data connection must go to the client")
+                    .await
+            }
+            None => self.reply(501, "Invalid address").await,
+        }
+    }
+
+    async fn open_data(&mut self) -> io::Result<TcpStream> {
+        let stream = match self.data.take() {
+            Some(DataChannel::Passive(listener)) => timeout(DATA_TIMEOUT, listener.accept())
+                .await
+                .map(|accepted| accepted.map(|(stream, _)| stream)),
+            Some(DataChannel::Active(address)) => {
+                timeout(DATA_TIMEOUT, TcpStream::connect(address)).await
+            }
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::NotConnected,
+                    "use PASV or PORT first",
+                ))
+            }
+        };
+        stream.unwrap_or_else(|_| Err(io::Error::from(ErrorKind::TimedOut)))
+    }
+
+    // sends `data` over a fresh data connection
+    async fn send_data(&mut self, data: &[u8]) -> io::Result<()> {
+        self.reply(150, "Opening data connection").await?;
+        let result = match self.open_data().await {
+            Ok(mut stream) => match stream.write_all(data).await {
+                Ok(_) => stream.shutdown().await,
+                Err(err) => Err(err),
+            },
+            Err(err) => return self.reply(425, &err.to_string()).await,
+        };
+        match result {
+            Ok(_) => self.reply(226, "Transfer complete").await,
+            Err(err) => self.reply(426, &err.to_string()).await,
+        }
+    }
+
+    async fn list(&mut self, command: &str, arg: &str) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        let real = match self.real_path(&path).await {
+            Ok(real) => real,
+            Err(err) => return self.reply(550, &err.to_string()).await,
+        };
+        let metadata = match fs::metadata(&real).await {
+            Ok(metadata) => metadata,
+            Err(err) => return self.reply(550, &err.to_string()).await,
+        };
+        let mut entries = Vec::new();
+        if metadata.is_dir() {
+            let mut dir = match fs::read_dir(&real).await {
+                Ok(dir) => dir,
+                Err(err) => return self.reply(550, &err.to_string()).await,
+            };
+            while let Ok(Some(item)) = dir.next_entry().await {
+                // links are followed, dangling ones and those leading out of
+                // the shared directory left out
+                if self.check_inside(&item.path()).await.is_err() {
+                    continue;
+                }
+                if let Ok(metadata) = fs::metadata(item.path()).await {
+                    let name = item.file_name().to_string_lossy().to_string();
+                    entries.push(local_entry(name, &metadata));
+                }
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        } else if command == "MLSD" {
+            return self.reply(501, "Not a directory").await;
+        } else {
+            let name = real
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            entries.push(local_entry(name, &metadata));
+        }
+
+        let now = timestamp();
+        let mut text = String::new();
+        for entry in &entries {
+            let line = match command {
+                "NLST" => entry.name.clone(),
+                "MLSD" => format_mlsd_line(entry),
+                _ => format_list_line(entry, now),
+            };
+            text.push_str(&line);
+            text.push_str("\r\n");
+        }
+        self.send_data(text.as_bytes()).await
+    }
+
+    async fn mlst(&mut self, arg: &str) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        match async { fs::metadata(self.real_path(&path).await?).await }.await {
+            Ok(metadata) => {
+                let entry = local_entry(path.clone(), &metadata);
+                let text = format!(
+                    "250-Listing {}\r\n {}\r\n250 End\r\n",
+                    path,
+                    format_mlsd_line(&entry)
+                );
+                self.send(&text).await
+            }
+            Err(err) => self.reply(550, &err.to_string()).await,
+        }
+    }
+
+    async fn file_fact(&mut self, command: &str, arg: &str) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        match async { fs::metadata(self.real_path(&path).await?).await }.await {
+            Ok(metadata) if metadata.is_file() => {
+                let entry = local_entry(String::new(), &metadata);
+                let text = match command {
+                    "SIZE" => entry.size.to_string(),
+                    _ => format_mlsx_time(entry.modified),
+                };
+                self.reply(213, &text).await
+            }
+            Ok(_) => self.reply(550, "Not a regular file").await,
+            Err(err) => self.reply(550, &err.to_string()).await,
+        }
+    }
+
+    fn begin_transfer(&self, direction: &str, path: &str, total: u64) {
+        let transfer = FtpServerTransfer {
+            direction: direction.to_string(),
+            path: path.to_string(),
+            transferred: 0,
+            total,
+            started_at: timestamp(),
+        };
+        self.update(|client| client.transfer = Some(transfer));
+        notify(&self.app);
+    }
+
+    fn end_transfer(&self) {
+        self.update(|client| client.transfer = None);
+        notify(&self.app);
+    }
+
+    // copies until `reader` ends, keeping the client's transfer up to date;
+    // progress goes out at most every PROGRESS_INTERVAL
+    async fn pump<R, W>(&self, reader: &mut R, writer: &mut W) -> io::Result<u64>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut total = 0;
+        let mut notified = Instant::now();
+        loop {
+            let size = reader.read(&mut buffer).await?;
+            if size == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..size]).await?;
+            total += size as u64;
+            self.update(|client| {
+                if let Some(transfer) = client.transfer.as_mut() {
+                    transfer.transferred = total;
+                }
+            });
+            if notified.elapsed() >= PROGRESS_INTERVAL {
+                notify(&self.app);
+                notified = Instant::now();
+            }
+        }
+        writer.flush().await?;
+        Ok(total)
+    }
+
+    async fn retrieve(&mut self, arg: &str, rest: u64) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        let mut file = match async { fs::File::open(self.real_path(&path).await?).await }.await {
+            Ok(file) => file,
+            Err(err) => return self.reply(550, &err.to_string()).await,
+        };
+        let size = match file.metadata().await {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            Ok(_) => return self.reply(550, "Not a regular file").await,
+            Err(err) => return self.reply(550, &err.to_string()).await,
+        };
+        if rest > 0 {
+            if let Err(err) = file.seek(SeekFrom::Start(rest)).await {
+                return self.reply(554, &err.to_string()).await;
+            }
+        }
+        self.reply(150, "Opening data connection").await?;
+        let mut stream = match self.open_data().await {
+            Ok(stream) => stream,
+            Err(err) => return self.reply(425, &err.to_string()).await,
+        };
+        self.begin_transfer(DIRECTION_DOWNLOAD, &path, size.saturating_sub(rest));
+        let result = match self.pump(&mut file, &mut stream).await {
+            Ok(_) => stream.shutdown().await,
+            Err(err) => Err(err),
+        };
+        self.end_transfer();
+        match result {
+            Ok(_) => self.reply(226, "Transfer complete").await,
+            Err(err) => self.reply(426, &err.to_string()).await,
+        }
+    }
+
+    async fn store(&mut self, arg: &str, rest: u64, append: bool) -> io::Result<()> {
+        let path = resolve_path(&self.cwd, arg);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true);
+        match (append, rest) {
+            (true, _) => options.append(true),
+            (false, 0) => options.truncate(true),
+            _ => &mut options,
+        };
+        let mut file = match async { options.open(self.real_path(&path).await?).await }.await {
+            Ok(file) => file,
+            Err(err) => return self.reply(550, &err.to_string()).await,
+        };
+        if rest > 0 {
+            // a resumed upload replaces whatever followed the offset
+            let resumed = async {
+                file.set_len(rest).await?;
+                file.seek(SeekFrom::Start(rest)).await
+            };
+            if let Err(err) = resumed.await {
+                return self.reply(554, &err.to_string()).await;
+            }
+        }
+        self.reply(150, "Opening data connection").await?;
+        let mut stream = match self.open_data().await {
+            Ok(stream) => stream,
+            Err(err) => return self.reply(425, &err.to_string()).await,
+        };
+        self.begin_transfer(DIRECTION_UPLOAD, &path, 0);
+        let result = self.pump(&mut stream, &mut file).await;
+        self.end_transfer();
+        match result {
+            Ok(_) => self.reply(226, "Transfer complete").await,
+            Err(err) => self.reply(426, &err.to_string()).await,
+        }
+    }
+}
+
+async fn accept_clients(
+    app: AppHandle,
+    config: Arc<ServerConfig>,
+    listener: TcpListener,
+    stop: watch::Receiver<()>,
+) {
+    loop {
+        let mut signal = stop.clone();
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // out of file descriptors and the like, try again shortly
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = signal.changed() => return,
+        };
+        let Ok(local) = stream.local_addr() else {
+            continue;
+        };
+        let client_id = Uuid::new_v4().to_string();
+        update_client(&config.server_id, |clients| {
+            clients.insert(
+                client_id.clone(),
+                FtpServerClient {
+                    client_id: client_id.clone(),
+                    address: peer.to_string(),
+                    username: None,
+                    connected_at: timestamp(),
+                    cwd: String::from("/"),
+                    transfer: None,
+                },
+            );
+        });
+        notify(&app);
+
+        let (reader, writer) = stream.into_split();
+        let session = Session {
+            app: app.clone(),
+            config: config.clone(),
+            client_id: client_id.clone(),
+            writer,
+            local_ip: local.ip(),
+            peer,
+            username: None,
+            logged_in: false,
+            cwd: String::from("/"),
+            data: None,
+            rest: 0,
+            rename_from: None,
+        };
+        let app = app.clone();
+        let server_id = config.server_id.clone();
+        let mut signal = stop.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = session.run(reader) => {}
+                _ = signal.changed() => {}
+            }
+            update_client(&server_id, |clients| {
+                clients.remove(&client_id);
+            });
+            notify(&app);
+        });
+    }
+}
+
+// shares `root` over plain FTP on all interfaces; one server at a time, its
+// clients are reported through "ftp_server_status" events
+#[tauri::command]
+pub async fn start_ftp_server(
+    app: AppHandle,
+    root: String,
+    options: Option<FtpServerOptions>,
+) -> Result<FtpServerInfo, String> {
+    let options = options.unwrap_or_default();
+    let root_path = fs::canonicalize(&root)
+        .await
+        .map_err(|e| format!("共享目录不存在: {}", e))?;
+    if !root_path.is_dir() {
+        return Err(String::from("共享路径不是目录"));
+    }
+    let passive_ports = match (options.passive_port_start, options.passive_port_end) {
+        (Some(start), Some(end)) if start <= end => Some((start, end)),
+        (None, None) => None,
+        _ => return Err(String::from("被动模式端口范围无效")),
+    };
+    let credentials = match options.username.filter(|username| !username.is_empty()) {
+        Some(username) => Some((username, options.password.unwrap_or_default())),
+        None => None,
+    };
+    if server_status().running {
+        return Err(String::from("FTP服务已在运行"));
+    }
+
+    let port = options.port.unwrap_or(DEFAULT_PORT);
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .map_err(|e| format!("监听端口失败: {}", e))?;
+    let info = FtpServerInfo {
+        root: root_path.to_string_lossy().to_string(),
+        port: listener
+            .local_addr()
+            .map(|addr| addr.port())
+            .unwrap_or(port),
+        anonymous: credentials.is_none(),
+        read_only: options.read_only.unwrap_or(false),
+        passive_ports,
+        started_at: timestamp(),
+    };
+    let config = Arc::new(ServerConfig {
+        server_id: Uuid::new_v4().to_string(),
+        root: root_path,
+        credentials,
+        read_only: info.read_only,
+        passive_ports,
+    });
+    let (stop, signal) = watch::channel(());
+    {
+        let mut server = FTP_SERVER.lock().map_err(|_| "锁获取失败")?;
+        if server.is_some() {
+            return Err(String::from("FTP服务已在运行"));
+        }
+        *server = Some(FtpServer {
+            server_id: config.server_id.clone(),
+            info: info.clone(),
+            clients: HashMap::new(),
+            _stop: stop,
+        });
+    }
+    tokio::spawn(accept_clients(app.clone(), config, listener, signal));
+    notify(&app);
+    Ok(info)
+}
+
+// closes the listener and every client connection
+#[tauri::command]
+pub async fn stop_ftp_server(app: AppHandle) -> Result<(), String> {
+    let server = FTP_SERVER.lock().map_err(|_| "锁获取失败")?.take();
+    if server.is_none() {
+        return Err(String::from("FTP服务未运行"));
+    }
+    drop(server);
+    notify(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn ftp_server_status() -> Result<FtpServerStatus, String> {
+    Ok(server_status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path_stays_in_root() {
+        assert_eq!(resolve_path("/", "docs"), "/docs");
+        assert_eq!(resolve_path("/docs", "a/./b"), "/docs/a/b");
+        assert_eq!(resolve_path("/docs", "/other"), "/other");
+        assert_eq!(resolve_path("/docs", "../../.."), "/");
+        assert_eq!(resolve_path("/docs", "..\\..\\etc"), "/etc");
+        assert_eq!(resolve_path("/docs/a", ""), "/docs/a");
+    }
+
+    #[test]
+    fn test_list_argument() {
+        assert_eq!(list_argument(""), "");
+        assert_eq!(list_argument("-la"), "");
+        assert_eq!(list_argument("-l -a docs"), "docs");
+        assert_eq!(list_argument("  docs/a b "), "docs/a b");
+    }
+
+    #[test]
+    fn test_parse_port() {
+        assert_eq!(
+            parse_port("127,0,0,1,4,1"),
+            Some("127.0.0.1:1025".parse().unwrap())
+        );
+        assert_eq!(parse_port("127,0,0,1,4"), None);
+        assert_eq!(parse_port("127,0,0,1,4,256"), None);
+        assert_eq!(parse_port("a,b,c,d,e,f"), None);
+    }
+
+    #[test]
+    fn test_parse_eprt() {
+        assert_eq!(
+            parse_eprt("|1|127.0.0.1|2121|"),
+            Some("127.0.0.1:2121".parse().unwrap())
+        );
+        assert_eq!(parse_eprt("!2!::1!21!"), Some("[::1]:21".parse().unwrap()));
+        assert_eq!(parse_eprt("|1|127.0.0.1|70000|"), None);
+        assert_eq!(parse_eprt("|1|127.0.0.1|"), None);
+        assert_eq!(parse_eprt(""), None);
+    }
+}
@@ -1,12 +1,27 @@
 pub mod define;
 pub mod docker;
 pub mod file;
+pub mod file_system;
 pub mod http_request;
 pub mod http_server;
 pub mod js;
 pub mod network;
 pub mod ssh;
+pub mod ssh_auth;
+pub mod ssh_exec;
+pub mod ssh_file;
+pub mod ssh_known_hosts;
+pub mod ssh_metrics;
+pub mod ssh_profile;
+pub mod ssh_session;
+pub mod ssh_sync;
+pub mod ssh_tail;
+pub mod ssh_terminal;
+pub mod ssh_transfer;
+pub mod ssh_tunnel;
+pub mod tree;
 pub mod ftp;
+pub mod ftp_server;
 pub mod webview;
 pub mod work;
 pub mod opener;
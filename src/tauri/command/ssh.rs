@@ -1,371 +1,638 @@
 use super::define::InvokeResponse;
+use super::ssh_auth::{authenticate, SshAuth};
+use super::ssh_exec::exec_collect;
+use super::ssh_known_hosts::{prefer_host_key_types, verify_host_key};
+use super::ssh_session::{emit_session_status, forget_session, record_session, touch_session};
+use super::ssh_tunnel::{pump_channel, TunnelState};
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use ssh2::DisconnectCode::AuthCancelledByUser;
-use ssh2::Session;
-use std::borrow::BorrowMut;
+use ssh2::{ErrorCode, FileStat, FileType, Session, Sftp};
 use std::collections::HashMap;
-use std::fs;
 use std::io::prelude::*;
-use std::io::{BufRead, BufReader};
-use std::net::TcpStream;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tauri::{AppHandle, Emitter};
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct TransferInfo {
-    local_file: String,
-    remote_file: String,
-    total: u64,
-    current: u64,
-    status: String,
-    message: String,
-}
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
 
 lazy_static! {
     pub static ref SESSION_MAP: Arc<Mutex<HashMap<String, Session>>> =
         Arc::new(Mutex::new(HashMap::new()));
-    pub static ref UPLOAD_PROGRESS: Arc<Mutex<(u64, u64)>> = Arc::new(Mutex::new((100, 0)));
-    pub static ref TRANSFER_INFO: Arc<Mutex<TransferInfo>> = Arc::new(Mutex::new(TransferInfo {
-        local_file: String::new(),
-        remote_file: String::new(),
-        total: 0,
-        current: 0,
-        status: String::new(),
-        message: String::new(),
-    }));
-}
-
-fn init_transfer_info(local_file: String, remote_file: String, total_size: u64) {
-    let mut transfer_info = TRANSFER_INFO.lock().unwrap();
-    let transfer_info = transfer_info.borrow_mut();
-    transfer_info.local_file = local_file;
-    transfer_info.remote_file = remote_file;
-    transfer_info.total = total_size;
-    transfer_info.current = 0;
-    transfer_info.status = String::from("transferring");
-    transfer_info.message = String::from("");
-}
-
-fn incr_transfer_size(size: u64) {
-    let mut transfer_info = TRANSFER_INFO.lock().unwrap();
-    let transfer_info = transfer_info.borrow_mut();
-    transfer_info.current = transfer_info.current + size;
-}
-
-fn mark_transfer_failure(message: String) {
-    let mut transfer_info = TRANSFER_INFO.lock().unwrap();
-    let transfer_info = transfer_info.borrow_mut();
-    transfer_info.status = String::from("failure");
-    transfer_info.message = message;
-}
-
-fn mark_transfer_success() {
-    let mut transfer_info = TRANSFER_INFO.lock().unwrap();
-    let transfer_info = transfer_info.borrow_mut();
-    transfer_info.status = String::from("success");
-    transfer_info.current = transfer_info.total;
-    transfer_info.message = String::from("");
-}
-
-static mut CANCEL_SIGNAL: i32 = 0;
-const BUFFER_SIZE: usize = 1024;
+    // keyed by the address of the libssh2 session, see `call_lock`
+    static ref CALL_LOCKS: Mutex<HashMap<usize, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
 const AUTH_TYPE_PASSWORD: &str = &"password";
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+const CALL_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+// LIBSSH2_ERROR_EAGAIN and LIBSSH2_ERROR_BAD_USE
+const ERROR_EAGAIN: i32 = -37;
+const ERROR_BAD_USE: i32 = -39;
+const BAD_USE_RETRIES: u32 = 2000;
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct File {
-    access: String,
-    group: String,
-    user: String,
-    size: String,
-    month: String,
-    day: String,
-    time: String,
     name: String,
+    path: String,
+    file_type: String,
+    size: u64,
+    mtime: u64,
+    atime: u64,
+    mode: u32,
+    permissions: String,
+    uid: u32,
+    gid: u32,
+    link_target: Option<String>,
     is_dir: bool,
 }
 
-/*
--rw-------. 1 roo roo 2897 Jun 16 15:20 anaconda-ks.cfg
-drwxrwxr-x 13 501 games 4096 Jul 20 15:54 download
--rw-r--r-- 1 roo roo 1024507392 Jul 20 15:57 download. ar
--rw-r--r--. 1 roo roo 2723 Jun 16 15:19 ini .log
--rw-r--r--. 1 roo roo 16108 Jun 16 15:20 ks-pos .log
--rw-------. 1 roo roo 2832 Jun 16 15:20 original-ks.cfg
-drwx------ 4 501 games 4096 Jan 29 17:17 projec -src
--rw-r--r-- 1 roo roo 48936960 Jul 7 14:53 realsee-open-admin. ar
--rw-r--r-- 1 roo roo 77155328 Jul 6 18:20 realsee-open-svc. ar
--rw-r--r-- 1 roo roo 67422720 Jul 7 15:23 realsee-shepherd-alg. ar
--rw-r--r-- 1 roo roo 67352576 Jun 27 17:59 realsee-shepherd-svc. ar
--rw-r--r-- 1 roo roo 706563 Jun 19 14:33 realsee-vr-local.2.3.0-5cf4997-nuc. ar.gz
-*/
-#[tauri::command]
-pub async fn remote_list_files(session_key: String, path: String) -> Result<Vec<File>, String> {
+pub fn get_session(session_key: &str) -> Result<Session, String> {
     let list = SESSION_MAP
         .lock()
         .map_err(|e| format!("get session map error:{}", e))?;
-    let session = list.get(&session_key).ok_or("no session")?;
-    let mut channel = session
-        .channel_session()
-        .map_err(|e| format!("get channel error:{}", e))?;
-    channel
-        .exec(&format!("ls -l {}", path))
-        .map_err(|e| format!("exec error:{}", e))?;
-    let mut result = String::new();
-    _ = channel
-        .read_to_string(&mut result)
-        .map_err(|e| format!("read error:{}", e))?;
-    let list: Vec<&str> = result.split("\n").collect();
-    let mut file_list: Vec<File> = Vec::new();
-    for item in list.iter() {
-        let parts: Vec<&str> = item.split(" ").filter(|x| x.len() > 0).collect();
-        if parts.len() < 9 {
-            continue;
-        }
-        file_list.push(File {
-            access: String::from(parts[0]),
-            group: String::from(parts[2]),
-            user: String::from(parts[3]),
-            size: String::from(parts[4]),
-            month: String::from(parts[5]),
-            day: String::from(parts[6]),
-            time: String::from(parts[7]),
-            name: String::from(parts[8..].join(" ")),
-            is_dir: parts[0].starts_with("d"),
-        })
-    }
-    Ok(file_list)
+    let session = list
+        .get(session_key)
+        .cloned()
+        .ok_or_else(|| String::from("no session"))?;
+    drop(list);
+    touch_session(session_key);
+    Ok(session)
 }
 
-#[tauri::command]
-pub async fn download_remote_file(
-    session_key: String,
-    local_file: String,
-    remote_file: String,
-) -> Result<String, String> {
-    let list = SESSION_MAP
-        .lock()
-        .map_err(|e| format!("get session map error:{}", e))?;
-    let session = list.get(&session_key).ok_or("no session")?;
-
-    // 1. get remote file size
-    let (mut remote_channel, stat) = session
-        .scp_recv(Path::new(&remote_file.as_str()))
-        .map_err(|e| format!("scp recv error:{}", e))?;
-    println!("download remote file:{} size:{}", remote_file, stat.size());
-    let mut buffer = [0u8; BUFFER_SIZE];
-    let file_path = Path::new(&local_file);
-    // 2. create dir if not exists
-    let save_dir = file_path.parent().ok_or("no parent dir")?;
-    fs::create_dir_all(save_dir).map_err(|e| format!("create dir error:{}", e))?;
-    let mut tmp_file =
-        fs::File::create(file_path).map_err(|e| format!("create file error:{}", e))?;
-    tokio::spawn(async move {
-        init_transfer_info(local_file, remote_file, stat.size() as u64);
-        let mut download_error = false;
-        loop {
-            unsafe {
-                println!("CANCEL_SIGNAL:{}", CANCEL_SIGNAL);
-                if CANCEL_SIGNAL > 0 {
-                    download_error = true;
-                    break;
-                }
-            }
-            println!("download loop");
-            let result = remote_channel.read(buffer.as_mut_slice());
-            if let Err(err) = result {
-                println!("download error:{}", err);
-                mark_transfer_failure(err.to_string());
-                download_error = true;
-                break;
-            }
+// libssh2 keeps half-finished requests (a channel being opened, an sftp
+// handshake, a partly sent packet) in the session, so a request that ran into
+// EAGAIN has to be repeated before any other one is started. Every call on a
+// shared session goes through this lock, ssh2's own lock only covers a
+// single call. A session that is still being set up, or one that has been
+// disconnected, is not shared and gets a lock of its own.
+fn call_lock(session: &Session) -> Arc<Mutex<()>> {
+    let locks = CALL_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks
+        .get(&call_lock_key(session))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn call_lock_key(session: &Session) -> usize {
+    &*session.raw() as *const _ as usize
+}
 
-            if let Ok(size) = result {
-                println!("download size: {}", size);
-                if size == 0 {
-                    break;
+// before a session is handed to other threads
+pub fn share_call_lock(session: &Session) {
+    let mut locks = CALL_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(call_lock_key(session)).or_default();
+}
+
+// once a session is disconnected, its address may be reused by a new one
+pub fn forget_call_lock(session: &Session) {
+    let mut locks = CALL_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.remove(&call_lock_key(session));
+}
+
+// a single call under the call lock, for polling reads and accepts
+pub fn attempt<T, F: FnOnce() -> T>(session: &Session, f: F) -> T {
+    let lock = call_lock(session);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    f()
+}
+
+enum Pending {
+    // EAGAIN: the request is under way, repeat it while holding the lock
+    Again,
+    // another request is still half sent, let its owner finish first
+    Busy,
+}
+
+fn call_until<T, E, F, C>(
+    session: &Session,
+    deadline: Option<Instant>,
+    mut f: F,
+    pending: C,
+) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    C: Fn(&E) -> Option<Pending>,
+{
+    let lock = call_lock(session);
+    let mut busy_retries = 0;
+    loop {
+        let guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let (result, busy) = loop {
+            let result = f();
+            match result.as_ref().err().and_then(&pending) {
+                Some(Pending::Again) if deadline.is_none_or(|d| Instant::now() < d) => {
+                    thread::sleep(CALL_RETRY_INTERVAL)
                 }
-                let _ = tmp_file.write(&buffer[..size]);
-                incr_transfer_size(size as u64);
+                Some(Pending::Busy) => break (result, true),
+                _ => break (result, false),
             }
+        };
+        drop(guard);
+        if !busy || busy_retries >= BAD_USE_RETRIES {
+            return result;
         }
-        if !download_error {
-            mark_transfer_success();
+        busy_retries += 1;
+        thread::sleep(CALL_RETRY_INTERVAL);
+    }
+}
+
+fn pending_error(err: &ssh2::Error) -> Option<Pending> {
+    match err.code() {
+        ErrorCode::Session(ERROR_EAGAIN) => Some(Pending::Again),
+        ErrorCode::Session(ERROR_BAD_USE) => Some(Pending::Busy),
+        _ => None,
+    }
+}
+
+// runs a request to completion on a non-blocking session
+pub fn retry<T, F>(session: &Session, f: F) -> Result<T, ssh2::Error>
+where
+    F: FnMut() -> Result<T, ssh2::Error>,
+{
+    call_until(session, None, f, pending_error)
+}
+
+// like `retry`, but gives up with the EAGAIN error once `timeout` has passed
+pub fn retry_for<T, F>(session: &Session, timeout: Duration, f: F) -> Result<T, ssh2::Error>
+where
+    F: FnMut() -> Result<T, ssh2::Error>,
+{
+    call_until(session, Some(Instant::now() + timeout), f, pending_error)
+}
+
+// `retry` for the Read/Write side of channels and sftp files, whose errors
+// only keep the kind; the session still has the libssh2 code
+pub fn retry_io<T, F>(session: &Session, f: F) -> std::io::Result<T>
+where
+    F: FnMut() -> std::io::Result<T>,
+{
+    call_until(session, None, f, |err: &std::io::Error| match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::Interrupted => Some(Pending::Again),
+        ErrorKind::Other => {
+            ssh2::Error::last_session_error(session).and_then(|last| pending_error(&last))
         }
-        remote_channel.send_eof().unwrap();
-        remote_channel.wait_eof().unwrap();
-        remote_channel.close().unwrap();
-        remote_channel.wait_close().unwrap();
-    });
-    Ok(String::from("success"))
+        _ => None,
+    })
 }
 
-#[tauri::command]
-pub async fn upload_remote_file(
-    session_key: String,
-    local_file: String,
-    remote_file: String,
-) -> Result<String, String> {
-    let list = SESSION_MAP.lock().unwrap();
-    let session = list.get(&session_key).ok_or("no session")?;
+// wraps a channel or sftp file so plain reads and writes wait for completion
+pub struct SessionStream<'a, T> {
+    session: &'a Session,
+    inner: T,
+}
 
-    let metadata = fs::metadata(&local_file.to_string()).map_err(|e| e.to_string())?;
-    if metadata.len() < 1 {
-        mark_transfer_failure(String::from("file empty"));
-        return Err(String::from("file empty"));
+impl<'a, T> SessionStream<'a, T> {
+    pub fn new(session: &'a Session, inner: T) -> SessionStream<'a, T> {
+        SessionStream { session, inner }
     }
+}
 
-    let mut remote_channel = session
-        .scp_send(
-            Path::new(&remote_file.as_str()),
-            0o644,
-            metadata.len(),
-            None,
-        )
-        .map_err(|e| e.to_string())?;
-    let tmp_file = fs::File::open(Path::new(&local_file.as_str())).map_err(|e| e.to_string())?;
-    //unsafe { CANCEL_SIGNAL = 0 }
-    tokio::spawn(async move {
-        let mut reader = BufReader::new(tmp_file); // 创建 BufReader
-        init_transfer_info(
-            local_file.to_string(),
-            remote_file.to_string(),
-            metadata.len(),
-        );
-        loop {
-            unsafe {
-                if CANCEL_SIGNAL > 0 {
-                    break;
-                }
-            }
-            let result = reader.fill_buf();
-            if let Err(err) = result {
-                mark_transfer_failure(err.to_string());
-                break;
-            }
-            let size = result.unwrap().len();
-            if size > 0 {
-                if let Err(err) = remote_channel.write(reader.buffer()) {
-                    mark_transfer_failure(err.to_string());
-                    break;
-                }
-            } else {
-                break;
-            }
-            reader.consume(size);
-            incr_transfer_size(size as u64);
+impl<T: Read> Read for SessionStream<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let inner = &mut self.inner;
+        retry_io(self.session, || inner.read(buf))
+    }
+}
+
+impl<T: Write> Write for SessionStream<'_, T> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let inner = &mut self.inner;
+        retry_io(self.session, || inner.write(data))
+    }
+
+    // no flush: on a channel it discards unread incoming data
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// a channel, listener, sftp file or sftp subsystem of a shared session.
+// ssh2 frees these on drop, files and the subsystem even switch the session
+// to blocking for it, so like any other call that runs under the call lock;
+// every such handle is kept in one of these to cover each way out.
+pub struct SessionHandle<T> {
+    session: Session,
+    // only empty while dropping
+    inner: Option<T>,
+}
+
+impl<T> SessionHandle<T> {
+    pub fn new(session: &Session, inner: T) -> SessionHandle<T> {
+        SessionHandle {
+            session: session.clone(),
+            inner: Some(inner),
         }
-        mark_transfer_success();
-        remote_channel.send_eof().unwrap();
-        remote_channel.wait_eof().unwrap();
-        remote_channel.close().unwrap();
-        remote_channel.wait_close().unwrap();
-    });
+    }
+}
 
-    Ok(String::from("success"))
+impl<T> Deref for SessionHandle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner.as_ref().expect("session handle used after drop")
+    }
 }
 
-#[tauri::command]
-pub async fn upload_remote_file_sync(
-    session_key: String,
-    local_file: String,
-    remote_file: String,
-) -> Result<String, String> {
-    let list = SESSION_MAP.lock().unwrap();
-    let session = list.get(&session_key).ok_or("no session")?;
+impl<T> DerefMut for SessionHandle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.inner.as_mut().expect("session handle used after drop")
+    }
+}
 
-    let metadata = fs::metadata(&local_file.to_string()).map_err(|e| e.to_string())?;
-    if metadata.len() < 1 {
-        mark_transfer_failure(String::from("file empty"));
-        return Err(String::from("file empty"));
+impl<T> Drop for SessionHandle<T> {
+    fn drop(&mut self) {
+        let inner = self.inner.take();
+        attempt(&self.session, || drop(inner));
     }
+}
 
-    let mut remote_channel = session
-        .scp_send(
-            Path::new(&remote_file.as_str()),
-            0o644,
-            metadata.len(),
-            None,
-        )
-        .map_err(|e| e.to_string())?;
-    let tmp_file = fs::File::open(Path::new(&local_file.as_str())).map_err(|e| e.to_string())?;
-    let mut reader = BufReader::new(tmp_file); // 创建 BufReader
-    init_transfer_info(
-        local_file.to_string(),
-        remote_file.to_string(),
-        metadata.len(),
-    );
-    loop {
-        let result = reader.fill_buf().map_err(|e| e.to_string())?;
-        if result.len() < 1 {
-            break;
-        }
-        let size = result.len();
-        remote_channel.write(reader.buffer()).map_err(|e| e.to_string())?;
-        reader.consume(size);
+impl<T: Read> Read for SessionHandle<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (**self).read(buf)
     }
+}
 
-    remote_channel.send_eof().unwrap();
-    remote_channel.wait_eof().unwrap();
-    remote_channel.close().unwrap();
-    remote_channel.wait_close().unwrap();
+impl<T: Write> Write for SessionHandle<T> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        (**self).write(data)
+    }
 
-    Ok(String::from("success"))
+    fn flush(&mut self) -> std::io::Result<()> {
+        (**self).flush()
+    }
 }
 
-fn connect_ssh_session(
-    user: &str,
-    host: &str,
-    port: &str,
-    auth_type: &str,
-    auth_config: &str,
-) -> Result<Session, String> {
-    let connection = TcpStream::connect(format!("{}:{}", host, port));
-    if let Err(err) = connection {
-        return Err(err.to_string());
+// an sftp subsystem with the session it runs on, so each request can take
+// the session's call lock
+pub struct SftpClient {
+    session: Session,
+    sftp: SessionHandle<Sftp>,
+}
+
+impl SftpClient {
+    pub fn open(session: &Session) -> Result<SftpClient, String> {
+        let sftp =
+            retry(session, || session.sftp()).map_err(|e| format!("open sftp error:{}", e))?;
+        Ok(SftpClient {
+            session: session.clone(),
+            sftp: SessionHandle::new(session, sftp),
+        })
     }
 
-    let mut session = Session::new().unwrap();
-    session.set_tcp_stream(connection.unwrap());
-    if let Err(err) = session.handshake() {
-        return Err(format!("handshake error:{}", err.to_string()));
+    pub fn session(&self) -> &Session {
+        &self.session
     }
-    if let Err(err) = session.auth_methods(user) {
-        return Err(format!("auth root error :{}", err.to_string()));
+
+    pub fn call<T, F>(&self, mut f: F) -> Result<T, ssh2::Error>
+    where
+        F: FnMut(&Sftp) -> Result<T, ssh2::Error>,
+    {
+        retry(&self.session, || f(&self.sftp))
     }
 
-    if AUTH_TYPE_PASSWORD.eq(auth_type) {
-        if let Err(err) = session.userauth_password(user, auth_config) {
-            return Err(format!(
-                "userauth_password error :{},{},{}",
-                err.to_string(),
-                user,
-                auth_config
-            ));
-        }
-    } else if let Err(err) = session.userauth_pubkey_file(user, None, Path::new(&auth_config), None)
+    // `call` for requests that open a file handle
+    pub fn open_file<F>(&self, f: F) -> Result<SessionHandle<ssh2::File>, ssh2::Error>
+    where
+        F: FnMut(&Sftp) -> Result<ssh2::File, ssh2::Error>,
     {
-        return Err(format!("userauth_pubkey_file error :{}", err.to_string()));
+        self.call(f)
+            .map(|file| SessionHandle::new(&self.session, file))
     }
 
-    if !session.authenticated() {
-        return Err(String::from("authenticated wrong"));
+    pub fn stream<T>(&self, inner: T) -> SessionStream<'_, T> {
+        SessionStream::new(&self.session, inner)
     }
+}
+
+// one read that returns WouldBlock instead of waiting, so a poll loop can
+// serve several streams and other threads get the session in between
+pub fn read_nonblocking(
+    session: &Session,
+    stream: &mut impl Read,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    attempt(session, || stream.read(buf))
+}
+
+// sessions and non-blocking sockets return WouldBlock when their buffer is full
+pub fn is_retryable(err: &std::io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::Interrupted
+}
 
+pub fn write_all_retrying(writer: &mut impl Write, mut data: &[u8]) -> std::io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(std::io::Error::from(ErrorKind::WriteZero)),
+            Ok(size) => data = &data[size..],
+            Err(err) if is_retryable(&err) => thread::sleep(RETRY_INTERVAL),
+            Err(err) => return Err(err),
+        }
+    }
+    // no flush: on a channel it discards unread incoming data
+    Ok(())
+}
+
+// decode the complete utf-8 prefix of `pending`, keeping a split trailing
+// character for the next chunk
+pub fn drain_utf8(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(text) => text.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    let text = String::from_utf8_lossy(&pending[..valid]).to_string();
+    pending.drain(..valid);
+    text
+}
+
+fn file_type_name(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::RegularFile => "file",
+        FileType::Directory => "directory",
+        FileType::Symlink => "symlink",
+        FileType::NamedPipe => "fifo",
+        FileType::CharDevice => "char_device",
+        FileType::BlockDevice => "block_device",
+        FileType::Socket => "socket",
+        FileType::Other(_) => "other",
+    }
+}
+
+// render mode bits the way `ls -l` does, e.g. drwxr-xr-x
+fn format_permissions(file_type: &FileType, mode: u32) -> String {
+    let mut text = String::from(match file_type {
+        FileType::Directory => "d",
+        FileType::Symlink => "l",
+        FileType::NamedPipe => "p",
+        FileType::CharDevice => "c",
+        FileType::BlockDevice => "b",
+        FileType::Socket => "s",
+        _ => "-",
+    });
+    let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    for (index, shift) in [6, 3, 0].iter().enumerate() {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 > 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 > 0 { 'w' } else { '-' });
+        let (flag, marker) = special[index];
+        text.push(match (bits & 0o1 > 0, mode & flag > 0) {
+            (true, true) => marker,
+            (false, true) => marker.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+pub fn sftp_file_info(sftp: &SftpClient, path: &Path, stat: &FileStat) -> File {
+    let file_type = stat.file_type();
+    let mut link_target = None;
+    let mut is_dir = file_type.is_dir();
+    if file_type.is_symlink() {
+        link_target = sftp
+            .call(|s| s.readlink(path))
+            .ok()
+            .map(|target| target.to_string_lossy().to_string());
+        // follow the link so directories behind symlinks can be opened
+        is_dir = sftp
+            .call(|s| s.stat(path))
+            .map(|target| target.is_dir())
+            .unwrap_or(false);
+    }
+    let mode = stat.perm.unwrap_or(0) & 0o7777;
+    File {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        path: path.to_string_lossy().to_string(),
+        permissions: format_permissions(&file_type, mode),
+        file_type: String::from(file_type_name(file_type)),
+        size: stat.size.unwrap_or(0),
+        mtime: stat.mtime.unwrap_or(0),
+        atime: stat.atime.unwrap_or(0),
+        mode,
+        uid: stat.uid.unwrap_or(0),
+        gid: stat.gid.unwrap_or(0),
+        link_target,
+        is_dir,
+    }
+}
+
+#[tauri::command]
+pub async fn remote_list_files(session_key: String, path: String) -> Result<Vec<File>, String> {
+    let session = get_session(&session_key)?;
+    let sftp = SftpClient::open(&session)?;
+    let entries = sftp
+        .call(|s| s.readdir(Path::new(&path)))
+        .map_err(|e| format!("read dir error:{}", e))?;
+    let mut file_list: Vec<File> = entries
+        .iter()
+        .map(|(file_path, stat)| sftp_file_info(&sftp, file_path, stat))
+        .collect();
+    file_list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(file_list)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SshConnectConfig {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    pub auth: SshAuth,
+    // bastions to go through, in order, before reaching `host`
+    #[serde(default)]
+    pub jump_hosts: Vec<SshJumpHost>,
+    // seconds between keepalive packets, 0 to disable
+    #[serde(default)]
+    pub keepalive_interval: u32,
+    #[serde(default)]
+    pub auto_reconnect: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SshJumpHost {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    pub auth: SshAuth,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+impl SshConnectConfig {
+    // the older commands pass the port as a string and either a password or a
+    // key file path
+    fn from_legacy(
+        user: &str,
+        host: &str,
+        port: &str,
+        auth_type: &str,
+        auth_config: &str,
+    ) -> Result<SshConnectConfig, String> {
+        let port: u16 = port.parse().map_err(|_| format!("invalid port:{}", port))?;
+        let auth = if AUTH_TYPE_PASSWORD.eq(auth_type) {
+            SshAuth::Password {
+                password: auth_config.to_string(),
+            }
+        } else {
+            SshAuth::KeyFile {
+                path: auth_config.to_string(),
+                passphrase: None,
+            }
+        };
+        Ok(SshConnectConfig {
+            host: host.to_string(),
+            port,
+            user: user.to_string(),
+            auth,
+            jump_hosts: Vec::new(),
+            keepalive_interval: 0,
+            auto_reconnect: false,
+        })
+    }
+}
+
+// without `via` this is a plain tcp connection; otherwise the stream is one
+// end of a loopback pair whose other end is pumped through a direct-tcpip
+// channel of `via`. The pump thread owns `via`, so each jump host stays
+// connected until the session stacked on it goes away.
+fn open_hop_stream(
+    via: Option<Session>,
+    host: &str,
+    port: u16,
+    app: Option<&AppHandle>,
+) -> Result<TcpStream, String> {
+    let via = match via {
+        Some(via) => via,
+        None => return TcpStream::connect((host, port)).map_err(|e| e.to_string()),
+    };
+    let mut channel = retry(&via, || via.channel_direct_tcpip(host, port, None))
+        .map(|channel| SessionHandle::new(&via, channel))
+        .map_err(|e| format!("open channel to {}:{} error:{}", host, port, e))?;
+    let listener = TcpListener::bind(("127.0.0.1", 0)).map_err(|e| e.to_string())?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    let connection = TcpStream::connect(address).map_err(|e| e.to_string())?;
+    let (mut socket, peer) = listener.accept().map_err(|e| e.to_string())?;
+    if Some(peer) != connection.local_addr().ok() {
+        return Err(format!(
+            "unexpected connection from {} on jump socket",
+            peer
+        ));
+    }
+    let app = app.cloned();
+    let host = host.to_string();
+    thread::spawn(move || {
+        let state = TunnelState::default();
+        if let Err(err) = pump_channel(&via, &mut channel, &mut socket, &state) {
+            if let Some(app) = &app {
+                let message = format!("jump host connection error:{}", err);
+                emit_session_status(app, "", &host, port, false, message);
+            }
+        }
+        let _ = attempt(&via, || via.disconnect(None, "jump closed", None));
+    });
+    Ok(connection)
+}
+
+fn start_session(
+    connection: TcpStream,
+    host: &str,
+    port: u16,
+    user: &str,
+    auth: &SshAuth,
+    app: Option<&AppHandle>,
+) -> Result<Session, String> {
+    let mut session = Session::new().map_err(|e| e.to_string())?;
+    session.set_tcp_stream(connection);
+    prefer_host_key_types(&session);
+    if let Err(err) = session.handshake() {
+        return Err(format!("handshake error:{}", err));
+    }
+    verify_host_key(&session, host, port, app)?;
+    authenticate(&session, host, user, auth, app)?;
+    // from here on the session is shared between threads; ssh2 holds its lock
+    // for the whole of a blocking call, so every call runs non-blocking
+    // through `retry` or `attempt` instead
+    session.set_blocking(false);
     Ok(session)
 }
 
+// a stream to `host` through every jump host in turn, or a direct connection
+// when there are none
+pub fn open_jump_stream(
+    jump_hosts: &[SshJumpHost],
+    host: &str,
+    port: u16,
+    app: Option<&AppHandle>,
+) -> Result<TcpStream, String> {
+    let mut previous: Option<Session> = None;
+    for hop in jump_hosts {
+        let connection = open_hop_stream(previous.take(), &hop.host, hop.port, app)
+            .map_err(|e| format!("jump host {} error:{}", hop.host, e))?;
+        let session = start_session(connection, &hop.host, hop.port, &hop.user, &hop.auth, app)
+            .map_err(|e| format!("jump host {} error:{}", hop.host, e))?;
+        previous = Some(session);
+    }
+    open_hop_stream(previous, host, port, app)
+}
+
+pub fn connect_ssh_session(
+    config: &SshConnectConfig,
+    app: Option<&AppHandle>,
+) -> Result<Session, String> {
+    let connection = open_jump_stream(&config.jump_hosts, &config.host, config.port, app)?;
+    start_session(
+        connection,
+        &config.host,
+        config.port,
+        &config.user,
+        &config.auth,
+        app,
+    )
+}
+
+pub fn store_session(
+    key: Option<String>,
+    session: Session,
+    config: &SshConnectConfig,
+    app: Option<&AppHandle>,
+) -> Result<String, String> {
+    let session_key = match key {
+        Some(key) if !key.is_empty() => key,
+        _ => Alphanumeric.sample_string(&mut rand::thread_rng(), 16),
+    };
+    share_call_lock(&session);
+    record_session(&session_key, &session, config, app);
+    SESSION_MAP
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(session_key.clone(), session);
+    Ok(session_key)
+}
+
 #[tauri::command]
 pub async fn test_server_connect(
+    app: AppHandle,
     user: String,
     host: String,
     port: String,
     auth_type: String,
     auth_config: String,
 ) -> InvokeResponse {
-    let session = connect_ssh_session(&user, &host, &port, &auth_type, &auth_config);
+    let session = SshConnectConfig::from_legacy(&user, &host, &port, &auth_type, &auth_config)
+        .and_then(|config| connect_ssh_session(&config, Some(&app)));
     if let Err(err) = session {
         return InvokeResponse {
             success: false,
@@ -382,23 +649,32 @@ pub async fn test_server_connect(
 
 #[tauri::command]
 pub async fn ssh_connect_by_password(
+    app: AppHandle,
     user: String,
     host: String,
     port: String,
     password: String,
     key: String,
 ) -> Result<String, String> {
-    let session = connect_ssh_session(&user, &host, &port, &AUTH_TYPE_PASSWORD, &password)
-        .map_err(|e| e.to_string())?;
-    let mut session_key = key.clone();
-    if session_key.len() < 1 {
-        session_key = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
-    }
-    SESSION_MAP
-        .lock()
-        .unwrap()
-        .insert(session_key.clone(), session);
-    Ok(session_key)
+    let config = SshConnectConfig::from_legacy(&user, &host, &port, AUTH_TYPE_PASSWORD, &password)?;
+    let session = connect_ssh_session(&config, Some(&app))?;
+    store_session(Some(key), session, &config, Some(&app))
+}
+
+// keyboard-interactive auth waits on the frontend, so the handshake runs off
+// the async runtime
+#[tauri::command]
+pub async fn ssh_connect(
+    app: AppHandle,
+    config: SshConnectConfig,
+    key: Option<String>,
+) -> Result<String, String> {
+    let (session, config, app) = tokio::task::spawn_blocking(move || {
+        connect_ssh_session(&config, Some(&app)).map(|session| (session, config, app))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    store_session(key, session, &config, Some(&app))
 }
 
 #[tauri::command]
@@ -406,13 +682,9 @@ pub async fn remote_exec_command(
     session_key: String,
     cmd_string: String,
 ) -> Result<String, String> {
-    let list = SESSION_MAP.lock().map_err(|e| e.to_string())?;
-    let session = list.get(&session_key).ok_or("no session")?;
-    let mut channel = session.channel_session().map_err(|e| e.to_string())?;
-    channel.exec(&cmd_string).map_err(|e| e.to_string())?;
-    let mut result = String::new();
-    _ = channel.read_to_string(&mut result);
-    Ok(result)
+    let session = get_session(&session_key)?;
+    let output = exec_collect(&session, &cmd_string, None)?;
+    Ok(output.stdout)
 }
 
 #[tauri::command]
@@ -422,49 +694,46 @@ pub async fn exist_ssh_session(session_key: String) -> Result<bool, String> {
     Ok(true)
 }
 
-#[tauri::command]
-pub async fn get_transfer_remote_progress() -> Result<TransferInfo, String> {
-    let progress = TRANSFER_INFO.lock().map_err(|e| e.to_string())?;
-    Ok(progress.clone())
-}
-
-#[tauri::command]
-pub async fn send_cancel_signal() -> Result<bool, String> {
-    unsafe { CANCEL_SIGNAL = 1 }
-    Ok(true)
-}
-
 #[tauri::command]
 pub async fn disconnect_server(session_key: String) -> InvokeResponse {
-    let mut session_map = SESSION_MAP.lock();
-
-    match session_map.as_mut() {
-        Ok(list) => match list.get(&session_key) {
-            Some(sess) => {
-                if let Err(e) = sess.disconnect(Some(AuthCancelledByUser), &"user action", None) {
-                    return InvokeResponse {
-                        success: true,
-                        message: String::from(e.message()),
-                        data: json!(null),
-                    };
-                }
-                list.remove(&session_key);
-                InvokeResponse {
-                    success: true,
-                    message: String::from("success"),
-                    data: json!(null),
-                }
-            }
-            None => InvokeResponse {
+    // taken out first, the disconnect may take a while and other commands
+    // need the map meanwhile
+    let session = match SESSION_MAP.lock() {
+        Ok(mut list) => list.remove(&session_key),
+        Err(err) => {
+            return InvokeResponse {
                 success: true,
-                message: String::from("no session found"),
+                message: err.to_string(),
                 data: json!(null),
-            },
-        },
-        Err(err) => InvokeResponse {
+            }
+        }
+    };
+    let Some(sess) = session else {
+        return InvokeResponse {
             success: true,
-            message: err.to_string(),
+            message: String::from("no session found"),
+            data: json!(null),
+        };
+    };
+    let result = retry_for(&sess, DISCONNECT_TIMEOUT, || {
+        sess.disconnect(Some(AuthCancelledByUser), "user action", None)
+    });
+    if let Err(e) = result {
+        // still connected, so it stays available unless the key was reused
+        if let Ok(mut list) = SESSION_MAP.lock() {
+            list.entry(session_key).or_insert(sess);
+        }
+        return InvokeResponse {
+            success: true,
+            message: String::from(e.message()),
             data: json!(null),
-        },
+        };
+    }
+    forget_call_lock(&sess);
+    forget_session(&session_key);
+    InvokeResponse {
+        success: true,
+        message: String::from("success"),
+        data: json!(null),
     }
 }
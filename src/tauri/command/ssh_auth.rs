@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use ssh2::{KeyboardInteractivePrompt, Prompt, Session};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const AUTH_PROMPT_EVENT: &str = "ssh_auth_prompt";
+const AUTH_PROMPT_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SshAuth {
+    Password {
+        password: String,
+    },
+    KeyFile {
+        path: String,
+        passphrase: Option<String>,
+    },
+    Key {
+        private_key: String,
+        passphrase: Option<String>,
+    },
+    Agent,
+    KeyboardInteractive,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AuthPromptItem {
+    text: String,
+    echo: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AuthPromptRequest {
+    request_id: String,
+    host: String,
+    username: String,
+    instructions: String,
+    prompts: Vec<AuthPromptItem>,
+}
+
+lazy_static! {
+    static ref AUTH_PROMPTS: Arc<Mutex<HashMap<String, Sender<Vec<String>>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+// forwards keyboard-interactive challenges (2FA codes, OTPs) to the frontend
+// and waits for `answer_ssh_auth_prompt`
+struct FrontendPrompter {
+    app: AppHandle,
+    host: String,
+}
+
+impl KeyboardInteractivePrompt for FrontendPrompter {
+    fn prompt<'a>(
+        &mut self,
+        username: &str,
+        instructions: &str,
+        prompts: &[Prompt<'a>],
+    ) -> Vec<String> {
+        if prompts.is_empty() {
+            return Vec::new();
+        }
+        let request_id = Uuid::new_v4().to_string();
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut list) = AUTH_PROMPTS.lock() {
+            list.insert(request_id.clone(), sender);
+        }
+        let request = AuthPromptRequest {
+            request_id: request_id.clone(),
+            host: self.host.clone(),
+            username: username.to_string(),
+            instructions: instructions.to_string(),
+            prompts: prompts
+                .iter()
+                .map(|p| AuthPromptItem {
+                    text: p.text.to_string(),
+                    echo: p.echo,
+                })
+                .collect(),
+        };
+        let answers = match self.app.emit(AUTH_PROMPT_EVENT, request) {
+            Ok(_) => receiver
+                .recv_timeout(AUTH_PROMPT_TIMEOUT)
+                .unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        if let Ok(mut list) = AUTH_PROMPTS.lock() {
+            list.remove(&request_id);
+        }
+        answers
+    }
+}
+
+// tries every identity the agent holds, not just the first
+fn auth_with_agent(session: &Session, user: &str) -> Result<(), String> {
+    let mut agent = session.agent().map_err(|e| format!("agent error:{}", e))?;
+    agent
+        .connect()
+        .map_err(|e| format!("connect agent error:{}", e))?;
+    agent
+        .list_identities()
+        .map_err(|e| format!("list agent identities error:{}", e))?;
+    let identities = agent
+        .identities()
+        .map_err(|e| format!("list agent identities error:{}", e))?;
+    for identity in identities.iter() {
+        if agent.userauth(user, identity).is_ok() {
+            break;
+        }
+    }
+    let _ = agent.disconnect();
+    if session.authenticated() {
+        Ok(())
+    } else if identities.is_empty() {
+        Err(String::from("no identities found in the ssh agent"))
+    } else {
+        Err(String::from("no agent identity was accepted"))
+    }
+}
+
+#[cfg(unix)]
+fn auth_with_key_data(
+    session: &Session,
+    user: &str,
+    private_key: &str,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    session
+        .userauth_pubkey_memory(user, None, private_key, passphrase)
+        .map_err(|e| format!("userauth_pubkey_memory error :{}", e))
+}
+
+#[cfg(not(unix))]
+fn auth_with_key_data(
+    _session: &Session,
+    _user: &str,
+    _private_key: &str,
+    _passphrase: Option<&str>,
+) -> Result<(), String> {
+    Err(String::from(
+        "in-memory private keys are not supported on this platform",
+    ))
+}
+
+pub fn authenticate(
+    session: &Session,
+    host: &str,
+    user: &str,
+    auth: &SshAuth,
+    app: Option<&AppHandle>,
+) -> Result<(), String> {
+    if let Err(err) = session.auth_methods(user) {
+        return Err(format!("auth root error :{}", err));
+    }
+    match auth {
+        SshAuth::Password { password } => session
+            .userauth_password(user, password)
+            .map_err(|e| format!("userauth_password error :{}", e))?,
+        SshAuth::KeyFile { path, passphrase } => session
+            .userauth_pubkey_file(user, None, Path::new(path), passphrase.as_deref())
+            .map_err(|e| format!("userauth_pubkey_file error :{}", e))?,
+        SshAuth::Key {
+            private_key,
+            passphrase,
+        } => auth_with_key_data(session, user, private_key, passphrase.as_deref())?,
+        SshAuth::Agent => auth_with_agent(session, user)?,
+        SshAuth::KeyboardInteractive => {
+            let app = app.ok_or("keyboard-interactive auth needs the app handle")?;
+            let mut prompter = FrontendPrompter {
+                app: app.clone(),
+                host: host.to_string(),
+            };
+            session
+                .userauth_keyboard_interactive(user, &mut prompter)
+                .map_err(|e| format!("userauth_keyboard_interactive error :{}", e))?
+        }
+    }
+    if !session.authenticated() {
+        return Err(String::from("authenticated wrong"));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn answer_ssh_auth_prompt(
+    request_id: String,
+    responses: Vec<String>,
+) -> Result<(), String> {
+    let mut list = AUTH_PROMPTS.lock().map_err(|e| e.to_string())?;
+    let sender = list.remove(&request_id).ok_or("no auth prompt")?;
+    sender
+        .send(responses)
+        .map_err(|_| String::from("auth prompt expired"))
+}
@@ -0,0 +1,532 @@
+use super::ssh::{
+    attempt, drain_utf8, get_session, is_retryable, read_nonblocking, retry, SessionHandle,
+};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, Session};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+const EXEC_OUTPUT_EVENT: &str = "ssh_exec_output";
+const EXEC_EXIT_EVENT: &str = "ssh_exec_exit";
+const BATCH_PROGRESS_EVENT: &str = "ssh_batch_progress";
+const DEFAULT_BATCH_PARALLELISM: usize = 4;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const READ_BUFFER_SIZE: usize = 8192;
+const STREAM_NAMES: [&str; 2] = ["stdout", "stderr"];
+// first line on stderr of a killable exec, carrying the pid of the remote shell
+const PID_MARKER: &str = "rust_box_exec_pid=";
+const PID_LINE_LIMIT: usize = 64;
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ExecOptions {
+    env: Option<HashMap<String, String>>,
+    stdin: Option<String>,
+    // seconds
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExecInfo {
+    exec_id: String,
+    session_key: String,
+    command: String,
+    started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExecChunk {
+    exec_id: String,
+    stream: String,
+    data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExecExit {
+    exec_id: String,
+    exit_status: Option<i32>,
+    exit_signal: Option<String>,
+    timed_out: bool,
+    cancelled: bool,
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchHostResult {
+    session_key: String,
+    stdout: String,
+    stderr: String,
+    exit_status: Option<i32>,
+    duration_ms: u64,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchProgress {
+    batch_id: String,
+    finished: usize,
+    total: usize,
+    result: BatchHostResult,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchExecResult {
+    batch_id: String,
+    results: Vec<BatchHostResult>,
+}
+
+#[derive(PartialEq)]
+pub enum ExecEnd {
+    Finished,
+    TimedOut,
+    Cancelled,
+}
+
+struct Execution {
+    info: ExecInfo,
+    cancel: Arc<AtomicBool>,
+}
+
+lazy_static! {
+    static ref EXEC_MAP: Arc<Mutex<HashMap<String, Execution>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+// wrap a value in single quotes for a POSIX shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// most sshd configs only accept a few variables through AcceptEnv, so the
+// rest are set inline in front of the command
+pub fn open_exec_channel(
+    session: &Session,
+    command: &str,
+    env: Option<&HashMap<String, String>>,
+) -> Result<SessionHandle<Channel>, String> {
+    let mut channel = retry(session, || session.channel_session())
+        .map(|channel| SessionHandle::new(session, channel))
+        .map_err(|e| format!("get channel error:{}", e))?;
+    let mut inline = String::new();
+    for (name, value) in env.into_iter().flatten() {
+        if !valid_env_name(name) {
+            return Err(format!("invalid environment variable name:{}", name));
+        }
+        if retry(session, || channel.setenv(name, value)).is_err() {
+            inline.push_str(&format!("export {}={}; ", name, shell_quote(value)));
+        }
+    }
+    let command = format!("{}{}", inline, command);
+    retry(session, || channel.exec(&command)).map_err(|e| format!("exec error:{}", e))?;
+    Ok(channel)
+}
+
+// for long running commands that can be stopped: the login shell is replaced
+// by a POSIX sh that reports its pid first, see pump_exec. sshd starts the
+// login shell as a session leader, so that pid names the process group of
+// everything the command runs.
+pub fn open_killable_channel(
+    session: &Session,
+    command: &str,
+    env: Option<&HashMap<String, String>>,
+) -> Result<SessionHandle<Channel>, String> {
+    let script = format!("printf '{}%s\\n' $$ >&2; {}", PID_MARKER, command);
+    open_exec_channel(
+        session,
+        &format!("exec sh -c {}", shell_quote(&script)),
+        env,
+    )
+}
+
+struct PidLine {
+    pending: Option<Vec<u8>>,
+    pid: Option<u32>,
+}
+
+impl PidLine {
+    // hands back the part of a stderr chunk that the command wrote itself
+    fn strip(&mut self, data: &[u8]) -> Vec<u8> {
+        let Some(pending) = self.pending.as_mut() else {
+            return data.to_vec();
+        };
+        pending.extend_from_slice(data);
+        let Some(end) = pending.iter().position(|b| *b == b'\n') else {
+            // not the pid line after all, e.g. an error from a login script
+            if pending.len() > PID_LINE_LIMIT {
+                return self.flush();
+            }
+            return Vec::new();
+        };
+        let mut rest = self.flush();
+        let line = String::from_utf8_lossy(&rest[..end]).to_string();
+        if let Some(pid) = line
+            .strip_prefix(PID_MARKER)
+            .and_then(|pid| pid.parse().ok())
+        {
+            self.pid = Some(pid);
+            rest.drain(..=end);
+        }
+        rest
+    }
+
+    fn flush(&mut self) -> Vec<u8> {
+        self.pending.take().unwrap_or_default()
+    }
+}
+
+// ssh2 cannot send a channel "signal" request, and closing the channel leaves
+// a command without a pty running, so its group is killed from a second one
+fn kill_process_group(session: &Session, pid: u32) {
+    let command = format!("kill -TERM -{} 2>/dev/null", pid);
+    let _ = exec_collect(session, &command, Some(KILL_TIMEOUT));
+}
+
+// feeds stdin and hands every output chunk to `on_output` until the command
+// exits, the deadline passes or `cancel` is set; in the latter two cases a
+// channel from open_killable_channel has its remote command terminated too
+pub fn pump_exec<F: FnMut(usize, &[u8])>(
+    session: &Session,
+    channel: &mut Channel,
+    killable: bool,
+    stdin: &[u8],
+    deadline: Option<Instant>,
+    cancel: &AtomicBool,
+    mut on_output: F,
+) -> Result<ExecEnd, String> {
+    let mut buffer = [0u8; READ_BUFFER_SIZE];
+    let mut written = 0;
+    let mut eof_sent = false;
+    let mut pid_line = PidLine {
+        pending: killable.then(Vec::new),
+        pid: None,
+    };
+    loop {
+        let end = if cancel.load(Ordering::SeqCst) {
+            Some(ExecEnd::Cancelled)
+        } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some(ExecEnd::TimedOut)
+        } else {
+            None
+        };
+        if let Some(end) = end {
+            if let Some(pid) = pid_line.pid {
+                kill_process_group(session, pid);
+            }
+            return Ok(end);
+        }
+        let mut busy = false;
+        if written < stdin.len() {
+            // a full window would keep the call lock while the command waits
+            // for its output to be read, so stdin is polled like the output
+            match attempt(session, || channel.write(&stdin[written..])) {
+                Ok(size) => {
+                    written += size;
+                    busy = true;
+                }
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => return Err(format!("write stdin error:{}", err)),
+            }
+        } else if !eof_sent {
+            match attempt(session, || channel.send_eof()).map_err(std::io::Error::from) {
+                Ok(_) => eof_sent = true,
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => return Err(format!("send eof error:{}", err)),
+            }
+        }
+        for stream_id in 0..STREAM_NAMES.len() {
+            let result =
+                read_nonblocking(session, &mut channel.stream(stream_id as i32), &mut buffer);
+            match result {
+                Ok(0) => {}
+                Ok(size) if stream_id == 1 => {
+                    let data = pid_line.strip(&buffer[..size]);
+                    if !data.is_empty() {
+                        on_output(stream_id, &data);
+                    }
+                    busy = true;
+                }
+                Ok(size) => {
+                    on_output(stream_id, &buffer[..size]);
+                    busy = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(format!("read error:{}", err)),
+            }
+        }
+        if !busy && channel.eof() {
+            let rest = pid_line.flush();
+            if !rest.is_empty() {
+                on_output(1, &rest);
+            }
+            return Ok(ExecEnd::Finished);
+        }
+        if !busy {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+pub fn finish_channel(session: &Session, channel: &mut Channel) {
+    let _ = retry(session, || channel.close());
+    // the exit status arrives just before the channel closes
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        match attempt(session, || channel.wait_close()).map_err(std::io::Error::from) {
+            Err(err) if is_retryable(&err) => thread::sleep(POLL_INTERVAL),
+            _ => break,
+        }
+    }
+}
+
+// runs a command to completion and returns its whole output; the channel is
+// polled like a streaming exec so a chatty stderr cannot stall stdout
+pub fn exec_collect(
+    session: &Session,
+    command: &str,
+    timeout: Option<Duration>,
+) -> Result<ExecOutput, String> {
+    let mut channel = open_exec_channel(session, command, None)?;
+    let mut output: [Vec<u8>; 2] = [Vec::new(), Vec::new()];
+    let cancel = AtomicBool::new(false);
+    let end = pump_exec(
+        session,
+        &mut channel,
+        false,
+        &[],
+        timeout.map(|timeout| Instant::now() + timeout),
+        &cancel,
+        |stream_id, data| output[stream_id].extend_from_slice(data),
+    );
+    finish_channel(session, &mut channel);
+    if end? == ExecEnd::TimedOut {
+        return Err(format!("command timed out:{}", command));
+    }
+    Ok(ExecOutput {
+        stdout: String::from_utf8_lossy(&output[0]).to_string(),
+        stderr: String::from_utf8_lossy(&output[1]).to_string(),
+        exit_status: channel.exit_status().unwrap_or(-1),
+    })
+}
+
+fn run_exec(
+    app: AppHandle,
+    exec_id: String,
+    session: Session,
+    mut channel: SessionHandle<Channel>,
+    options: ExecOptions,
+    cancel: Arc<AtomicBool>,
+) {
+    let stdin = options.stdin.unwrap_or_default().into_bytes();
+    let deadline = options
+        .timeout
+        .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let mut pending: [Vec<u8>; 2] = [Vec::new(), Vec::new()];
+    let end = pump_exec(
+        &session,
+        &mut channel,
+        true,
+        &stdin,
+        deadline,
+        &cancel,
+        |stream_id, data| {
+            pending[stream_id].extend_from_slice(data);
+            let data = drain_utf8(&mut pending[stream_id]);
+            if !data.is_empty() {
+                let _ = app.emit(
+                    EXEC_OUTPUT_EVENT,
+                    ExecChunk {
+                        exec_id: exec_id.clone(),
+                        stream: STREAM_NAMES[stream_id].to_string(),
+                        data,
+                    },
+                );
+            }
+        },
+    );
+    finish_channel(&session, &mut channel);
+
+    let (finished, message) = match &end {
+        Ok(ExecEnd::Finished) => (true, String::new()),
+        Ok(ExecEnd::TimedOut) => (false, String::from("timed out")),
+        Ok(ExecEnd::Cancelled) => (false, String::from("cancelled")),
+        Err(err) => (false, err.clone()),
+    };
+    let exit_signal = channel
+        .exit_signal()
+        .ok()
+        .and_then(|signal| signal.exit_signal);
+    let exit_status = if finished && exit_signal.is_none() {
+        channel.exit_status().ok()
+    } else {
+        None
+    };
+    if let Ok(mut list) = EXEC_MAP.lock() {
+        list.remove(&exec_id);
+    }
+    let _ = app.emit(
+        EXEC_EXIT_EVENT,
+        ExecExit {
+            exec_id,
+            exit_status,
+            exit_signal,
+            timed_out: matches!(end, Ok(ExecEnd::TimedOut)),
+            cancelled: matches!(end, Ok(ExecEnd::Cancelled)),
+            message,
+        },
+    );
+}
+
+#[tauri::command]
+pub async fn exec_ssh_command(
+    app: AppHandle,
+    session_key: String,
+    command: String,
+    options: Option<ExecOptions>,
+) -> Result<String, String> {
+    let options = options.unwrap_or_default();
+    let session = get_session(&session_key)?;
+    let channel = open_killable_channel(&session, &command, options.env.as_ref())?;
+
+    let exec_id = Uuid::new_v4().to_string();
+    let cancel = Arc::new(AtomicBool::new(false));
+    EXEC_MAP.lock().map_err(|e| e.to_string())?.insert(
+        exec_id.clone(),
+        Execution {
+            info: ExecInfo {
+                exec_id: exec_id.clone(),
+                session_key,
+                command,
+                started_at: timestamp(),
+            },
+            cancel: cancel.clone(),
+        },
+    );
+    let id = exec_id.clone();
+    thread::spawn(move || run_exec(app, id, session, channel, options, cancel));
+    Ok(exec_id)
+}
+
+#[tauri::command]
+pub async fn cancel_ssh_exec(exec_id: String) -> Result<(), String> {
+    let list = EXEC_MAP.lock().map_err(|e| e.to_string())?;
+    let execution = list.get(&exec_id).ok_or("no execution")?;
+    execution.cancel.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_ssh_execs() -> Result<Vec<ExecInfo>, String> {
+    let list = EXEC_MAP.lock().map_err(|e| e.to_string())?;
+    Ok(list
+        .values()
+        .map(|execution| execution.info.clone())
+        .collect())
+}
+
+fn exec_on_host(session_key: &str, command: &str, timeout: Option<Duration>) -> BatchHostResult {
+    let started = Instant::now();
+    let output =
+        get_session(session_key).and_then(|session| exec_collect(&session, command, timeout));
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match output {
+        Ok(output) => BatchHostResult {
+            session_key: session_key.to_string(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+            exit_status: Some(output.exit_status),
+            duration_ms,
+            error: None,
+        },
+        Err(err) => BatchHostResult {
+            session_key: session_key.to_string(),
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_status: None,
+            duration_ms,
+            error: Some(err),
+        },
+    }
+}
+
+// runs `command` on every session, at most `parallelism` at a time; a
+// "ssh_batch_progress" event goes out as each host finishes and the results
+// come back in the order of `session_keys`
+#[tauri::command]
+pub async fn batch_exec_ssh_command(
+    app: AppHandle,
+    session_keys: Vec<String>,
+    command: String,
+    parallelism: Option<usize>,
+    timeout: Option<u64>,
+    batch_id: Option<String>,
+) -> Result<BatchExecResult, String> {
+    let batch_id = batch_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let slots = Arc::new(Semaphore::new(
+        parallelism.unwrap_or(DEFAULT_BATCH_PARALLELISM).max(1),
+    ));
+    let total = session_keys.len();
+    let finished = Arc::new(Mutex::new(0));
+    let timeout = timeout.map(Duration::from_secs);
+    let tasks: Vec<_> = session_keys
+        .into_iter()
+        .map(|session_key| {
+            let (app, slots, finished) = (app.clone(), slots.clone(), finished.clone());
+            let (command, batch_id) = (command.clone(), batch_id.clone());
+            tokio::spawn(async move {
+                let _permit = slots.acquire_owned().await;
+                let key = session_key.clone();
+                let result =
+                    tokio::task::spawn_blocking(move || exec_on_host(&key, &command, timeout))
+                        .await
+                        .unwrap_or_else(|err| BatchHostResult {
+                            session_key,
+                            stdout: String::new(),
+                            stderr: String::new(),
+                            exit_status: None,
+                            duration_ms: 0,
+                            error: Some(err.to_string()),
+                        });
+                let finished = finished.lock().map(|mut count| {
+                    *count += 1;
+                    *count
+                });
+                let _ = app.emit(
+                    BATCH_PROGRESS_EVENT,
+                    BatchProgress {
+                        batch_id,
+                        finished: finished.unwrap_or(0),
+                        total,
+                        result: result.clone(),
+                    },
+                );
+                result
+            })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(total);
+    for task in tasks {
+        results.push(task.await.map_err(|e| e.to_string())?);
+    }
+    Ok(BatchExecResult { batch_id, results })
+}
@@ -0,0 +1,238 @@
+use super::ssh::{get_session, write_all_retrying, SftpClient};
+use super::ssh_exec::{exec_collect, shell_quote};
+use crate::toolbox::string::{decode_text, encode_text};
+use serde::{Deserialize, Serialize};
+use ssh2::{FileStat, OpenFlags, OpenType, RenameFlags, Session};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteFileContent {
+    path: String,
+    content: String,
+    encoding: String,
+    size: u64,
+    mtime: u64,
+    mode: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteFileWrite {
+    path: String,
+    size: u64,
+    backup: Option<String>,
+}
+
+fn read_all(sftp: &SftpClient, path: &Path) -> Result<Vec<u8>, String> {
+    let mut file = sftp
+        .open_file(|s| s.open(path))
+        .map_err(|e| format!("open {} error:{}", path.display(), e))?;
+    let mut data = Vec::new();
+    sftp.stream(&mut file)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("read {} error:{}", path.display(), e))?;
+    Ok(data)
+}
+
+fn write_new(sftp: &SftpClient, path: &Path, data: &[u8], mode: u32) -> Result<(), String> {
+    let mut file = sftp
+        .open_file(|s| {
+            s.open_mode(
+                path,
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+                mode as i32,
+                OpenType::File,
+            )
+        })
+        .map_err(|e| format!("open {} error:{}", path.display(), e))?;
+    write_all_retrying(&mut sftp.stream(&mut file), data)
+        .map_err(|e| format!("write {} error:{}", path.display(), e))
+}
+
+fn set_mode(sftp: &SftpClient, path: &Path, mode: u32) {
+    let _ = sftp.call(|s| {
+        s.setstat(
+            path,
+            FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(mode),
+                atime: None,
+                mtime: None,
+            },
+        )
+    });
+}
+
+// the temp file belongs to the login user; true once it carries the owner of
+// the file it replaces, false when the server refuses the chown
+fn keep_owner(sftp: &SftpClient, path: &Path, existing: Option<&FileStat>) -> bool {
+    let Some((uid, gid)) = existing.and_then(|stat| stat.uid.zip(stat.gid)) else {
+        return true;
+    };
+    let current = sftp.call(|s| s.stat(path));
+    if current.is_ok_and(|stat| stat.uid == Some(uid) && stat.gid == Some(gid)) {
+        return true;
+    }
+    sftp.call(|s| {
+        s.setstat(
+            path,
+            FileStat {
+                size: None,
+                uid: Some(uid),
+                gid: Some(gid),
+                perm: None,
+                atime: None,
+                mtime: None,
+            },
+        )
+    })
+    .is_ok()
+}
+
+// SFTP v3 servers (OpenSSH among them) refuse to rename over an existing
+// file, so fall back to `mv -f`, which is atomic on the same filesystem
+fn replace_file(
+    session: &Session,
+    sftp: &SftpClient,
+    from: &Path,
+    to: &Path,
+) -> Result<(), String> {
+    let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+    if sftp.call(|s| s.rename(from, to, Some(flags))).is_ok() {
+        return Ok(());
+    }
+    let command = format!(
+        "mv -f -- {} {}",
+        shell_quote(&from.to_string_lossy()),
+        shell_quote(&to.to_string_lossy())
+    );
+    let output = exec_collect(session, &command, Some(Duration::from_secs(30)))?;
+    if output.exit_status != 0 {
+        return Err(format!("rename error:{}", output.stderr.trim()));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn read_remote_file(
+    session_key: String,
+    path: String,
+    max_size: Option<u64>,
+    encoding: Option<String>,
+) -> Result<RemoteFileContent, String> {
+    let session = get_session(&session_key)?;
+    tokio::task::spawn_blocking(move || {
+        let sftp = SftpClient::open(&session)?;
+        let remote_path = Path::new(&path);
+        let stat = sftp
+            .call(|s| s.stat(remote_path))
+            .map_err(|e| format!("stat {} error:{}", path, e))?;
+        if !stat.is_file() {
+            return Err(format!("{} is not a regular file", path));
+        }
+        let size = stat.size.unwrap_or(0);
+        let max_size = max_size.unwrap_or(DEFAULT_MAX_SIZE);
+        if size > max_size {
+            return Err(format!(
+                "{} is {} bytes, larger than the {} byte limit",
+                path, size, max_size
+            ));
+        }
+        let data = read_all(&sftp, remote_path)?;
+        let (content, encoding) = decode_text(&data, encoding.as_deref())?;
+        Ok(RemoteFileContent {
+            path,
+            content,
+            encoding,
+            size: data.len() as u64,
+            mtime: stat.mtime.unwrap_or(0),
+            mode: stat.perm.unwrap_or(0) & 0o7777,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// writes into a temp file next to the target and renames it over, so readers
+// never see a half written file; the original mode and owner are kept. A link
+// is followed so that it stays a link, and a file whose owner cannot be
+// carried over is rewritten in place instead
+#[tauri::command]
+pub async fn write_remote_file(
+    session_key: String,
+    path: String,
+    content: String,
+    encoding: Option<String>,
+    backup: Option<bool>,
+) -> Result<RemoteFileWrite, String> {
+    let session = get_session(&session_key)?;
+    let data = encode_text(&content, encoding.as_deref())?;
+    tokio::task::spawn_blocking(move || {
+        let sftp = SftpClient::open(&session)?;
+        let existing = sftp.call(|s| s.stat(Path::new(&path))).ok();
+        if existing.as_ref().is_some_and(|stat| stat.is_dir()) {
+            return Err(format!("{} is a directory", path));
+        }
+        let target = match existing {
+            Some(_) => sftp
+                .call(|s| s.realpath(Path::new(&path)))
+                .map_err(|e| format!("realpath {} error:{}", path, e))?,
+            None => PathBuf::from(&path),
+        };
+        let target = target.as_path();
+        let mode = existing
+            .as_ref()
+            .and_then(|stat| stat.perm)
+            .map(|perm| perm & 0o7777)
+            .unwrap_or(0o644);
+
+        let mut backup_path = None;
+        if backup.unwrap_or(false) && existing.is_some() {
+            let backup_file = format!("{}.bak", target.display());
+            let original = read_all(&sftp, target)?;
+            write_new(&sftp, Path::new(&backup_file), &original, mode)?;
+            set_mode(&sftp, Path::new(&backup_file), mode);
+            backup_path = Some(backup_file);
+        }
+
+        let name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or("invalid file path")?;
+        let temp = target.with_file_name(format!(
+            ".{}.{}.tmp",
+            name,
+            &Uuid::new_v4().simple().to_string()[..8]
+        ));
+        let written = write_new(&sftp, &temp, &data, mode).map(|_| {
+            // the umask may have narrowed the mode given at open
+            set_mode(&sftp, &temp, mode);
+            keep_owner(&sftp, &temp, existing.as_ref())
+        });
+        let result = match written {
+            Ok(true) => replace_file(&session, &sftp, &temp, target),
+            Ok(false) => {
+                let _ = sftp.call(|s| s.unlink(&temp));
+                write_new(&sftp, target, &data, mode)
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            let _ = sftp.call(|s| s.unlink(&temp));
+            return Err(err);
+        }
+        Ok(RemoteFileWrite {
+            path,
+            size: data.len() as u64,
+            backup: backup_path,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
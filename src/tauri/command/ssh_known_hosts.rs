@@ -0,0 +1,261 @@
+use super::ssh::{open_jump_stream, SshJumpHost};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use ssh2::{CheckResult, HashType, KnownHostFileKind, MethodType, Session};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter};
+
+const STATUS_TRUSTED: &str = "trusted";
+const STATUS_UNKNOWN: &str = "unknown";
+const STATUS_CHANGED: &str = "changed";
+// carries the HostKeyInfo of a key that stopped a connection, so the frontend
+// can offer to trust it without parsing the error
+const HOST_KEY_EVENT: &str = "ssh_host_key_unverified";
+
+// OpenSSH's preference order, so the key offered matches the one ssh stored
+const HOST_KEY_PREFERENCE: &str = "ssh-ed25519,ecdsa-sha2-nistp256,ecdsa-sha2-nistp384,ecdsa-sha2-nistp521,rsa-sha2-512,rsa-sha2-256,ssh-rsa";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostKeyInfo {
+    host: String,
+    port: u16,
+    key_type: String,
+    fingerprint: String,
+    key: String,
+    status: String,
+    known_hosts_file: String,
+}
+
+lazy_static! {
+    static ref KNOWN_HOSTS_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+fn known_hosts_path() -> Result<PathBuf, String> {
+    if let Some(path) = KNOWN_HOSTS_FILE.lock().map_err(|e| e.to_string())?.clone() {
+        return Ok(path);
+    }
+    let home = env::home_dir().ok_or("no home dir")?;
+    Ok(home.join(".ssh").join("known_hosts"))
+}
+
+// known_hosts names non-default ports as [host]:port
+fn known_host_name(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+// the raw key blob starts with its length-prefixed type name
+fn key_type_name(key: &[u8]) -> String {
+    if key.len() < 4 {
+        return String::from("unknown");
+    }
+    let len = u32::from_be_bytes([key[0], key[1], key[2], key[3]]) as usize;
+    key.get(4..4 + len)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+pub fn prefer_host_key_types(session: &Session) {
+    // unsupported names are dropped by libssh2, so failure here is harmless
+    let _ = session.method_pref(MethodType::HostKey, HOST_KEY_PREFERENCE);
+}
+
+pub fn inspect_host_key(session: &Session, host: &str, port: u16) -> Result<HostKeyInfo, String> {
+    let (key, _) = session.host_key().ok_or("server sent no host key")?;
+    let hash = session
+        .host_key_hash(HashType::Sha256)
+        .ok_or("hash host key error")?;
+    let path = known_hosts_path()?;
+    let mut known_hosts = session
+        .known_hosts()
+        .map_err(|e| format!("known hosts error:{}", e))?;
+    // libssh2 stops at the first line it cannot parse, so feed it line by line
+    if let Ok(content) = fs::read_to_string(&path) {
+        for line in content.lines() {
+            let _ = known_hosts.read_str(line, KnownHostFileKind::OpenSSH);
+        }
+    }
+    let status = match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => STATUS_TRUSTED,
+        CheckResult::Mismatch => STATUS_CHANGED,
+        CheckResult::NotFound => STATUS_UNKNOWN,
+        CheckResult::Failure => return Err(String::from("check known hosts failure")),
+    };
+    Ok(HostKeyInfo {
+        host: host.to_string(),
+        port,
+        key_type: key_type_name(key),
+        fingerprint: format!("SHA256:{}", general_purpose::STANDARD_NO_PAD.encode(hash)),
+        key: general_purpose::STANDARD.encode(key),
+        status: String::from(status),
+        known_hosts_file: path.to_string_lossy().to_string(),
+    })
+}
+
+pub fn verify_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    app: Option<&AppHandle>,
+) -> Result<(), String> {
+    let info = inspect_host_key(session, host, port)?;
+    if info.status == STATUS_TRUSTED {
+        return Ok(());
+    }
+    if let Some(app) = app {
+        let _ = app.emit(HOST_KEY_EVENT, info.clone());
+    }
+    match info.status.as_str() {
+        STATUS_CHANGED => Err(format!(
+            "host key for {} has changed, the server now offers {} {}; this may be a man-in-the-middle attack, remove the old key from {} only if the change is expected",
+            known_host_name(host, port),
+            info.key_type,
+            info.fingerprint,
+            info.known_hosts_file
+        )),
+        _ => Err(format!(
+            "unknown host key for {}: {} {}, trust it before connecting",
+            known_host_name(host, port),
+            info.key_type,
+            info.fingerprint
+        )),
+    }
+}
+
+// hosts behind a bastion are scanned through the same jump hosts they are
+// connected through; those have to be trusted already
+fn scan_host_key(
+    jump_hosts: &[SshJumpHost],
+    host: &str,
+    port: u16,
+    app: &AppHandle,
+) -> Result<HostKeyInfo, String> {
+    let connection = open_jump_stream(jump_hosts, host, port, Some(app))?;
+    let mut session = Session::new().map_err(|e| e.to_string())?;
+    session.set_tcp_stream(connection);
+    prefer_host_key_types(&session);
+    session
+        .handshake()
+        .map_err(|e| format!("handshake error:{}", e))?;
+    inspect_host_key(&session, host, port)
+}
+
+#[tauri::command]
+pub async fn get_known_hosts_file() -> Result<String, String> {
+    Ok(known_hosts_path()?.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn set_known_hosts_file(path: String) -> Result<(), String> {
+    let mut file = KNOWN_HOSTS_FILE.lock().map_err(|e| e.to_string())?;
+    *file = if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    };
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn scan_ssh_host_key(
+    app: AppHandle,
+    host: String,
+    port: u16,
+    jump_hosts: Option<Vec<SshJumpHost>>,
+) -> Result<HostKeyInfo, String> {
+    tokio::task::spawn_blocking(move || {
+        scan_host_key(&jump_hosts.unwrap_or_default(), &host, port, &app)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// the fingerprint is the one the user confirmed, so a key swapped in between
+// the scan and the confirmation is never trusted
+#[tauri::command]
+pub async fn trust_ssh_host_key(
+    app: AppHandle,
+    host: String,
+    port: u16,
+    fingerprint: String,
+    jump_hosts: Option<Vec<SshJumpHost>>,
+) -> Result<HostKeyInfo, String> {
+    let target = host.clone();
+    let mut info = tokio::task::spawn_blocking(move || {
+        scan_host_key(&jump_hosts.unwrap_or_default(), &target, port, &app)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if info.fingerprint != fingerprint {
+        return Err(format!(
+            "host key for {} is now {}, not {}",
+            known_host_name(&host, port),
+            info.fingerprint,
+            fingerprint
+        ));
+    }
+    if info.status == STATUS_CHANGED {
+        return Err(format!(
+            "{} already has a different key in {}, remove it first",
+            known_host_name(&host, port),
+            info.known_hosts_file
+        ));
+    }
+    if info.status == STATUS_UNKNOWN {
+        let path = PathBuf::from(&info.known_hosts_file);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        // keep an existing last line intact when it lacks a newline
+        let needs_newline = fs::read(&path)
+            .map(|content| !content.is_empty() && !content.ends_with(b"\n"))
+            .unwrap_or(false);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("open {} error:{}", info.known_hosts_file, e))?;
+        let line = format!(
+            "{}{} {} {}\n",
+            if needs_newline { "\n" } else { "" },
+            known_host_name(&host, port),
+            info.key_type,
+            info.key
+        );
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("write {} error:{}", info.known_hosts_file, e))?;
+        info.status = String::from(STATUS_TRUSTED);
+    }
+    Ok(info)
+}
+
+// drops plain-text entries for the host; hashed entries have to be removed
+// with `ssh-keygen -R`
+#[tauri::command]
+pub async fn forget_ssh_host_key(host: String, port: u16) -> Result<usize, String> {
+    let path = known_hosts_path()?;
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let name = known_host_name(&host, port);
+    let mut removed = 0;
+    let mut kept = String::new();
+    for line in content.lines() {
+        let patterns = line.split_whitespace().next().unwrap_or("");
+        if !line.starts_with('#') && patterns.split(',').any(|p| p == name) {
+            removed += 1;
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    if removed > 0 {
+        fs::write(&path, kept).map_err(|e| e.to_string())?;
+    }
+    Ok(removed)
+}
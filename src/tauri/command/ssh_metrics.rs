@@ -0,0 +1,612 @@
+use super::ssh::get_session;
+use super::ssh_exec::exec_collect;
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use ssh2::Session;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const METRICS_EVENT: &str = "ssh_host_metrics";
+const SECTION_MARK: &str = "--rust-box-metrics--";
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TOP_PROCESSES: usize = 10;
+const MIN_POLL_INTERVAL: u64 = 1;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CpuMetrics {
+    // percent over the sampling window, all cores together
+    usage: f64,
+    cores: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LoadAverage {
+    one: f64,
+    five: f64,
+    fifteen: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MemoryMetrics {
+    total: u64,
+    used: u64,
+    free: u64,
+    available: u64,
+    buffers: u64,
+    cached: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SwapMetrics {
+    total: u64,
+    used: u64,
+    free: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiskMetrics {
+    filesystem: String,
+    mount: String,
+    total: u64,
+    used: u64,
+    available: u64,
+    usage: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NetworkMetrics {
+    interface: String,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    // bytes per second
+    rx_rate: f64,
+    tx_rate: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProcessMetrics {
+    pid: u32,
+    user: String,
+    cpu: f64,
+    memory: f64,
+    rss: u64,
+    command: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostMetrics {
+    timestamp: u64,
+    uptime: u64,
+    cpu: CpuMetrics,
+    load: LoadAverage,
+    memory: MemoryMetrics,
+    swap: SwapMetrics,
+    disks: Vec<DiskMetrics>,
+    network: Vec<NetworkMetrics>,
+    processes: Vec<ProcessMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MetricsSample {
+    monitor_id: String,
+    session_key: String,
+    metrics: Option<HostMetrics>,
+    error: Option<String>,
+}
+
+// counters that only mean something as a difference between two samples
+#[derive(Clone, Default)]
+struct Counters {
+    cpu_total: u64,
+    cpu_idle: u64,
+    network: HashMap<String, (u64, u64)>,
+    taken_at: Option<Instant>,
+}
+
+lazy_static! {
+    static ref MONITOR_MAP: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+fn counter_script() -> String {
+    format!(
+        "head -n 1 /proc/stat; echo {mark}; cat /proc/net/dev; echo {mark}",
+        mark = SECTION_MARK
+    )
+}
+
+// GNU ps can sort by cpu itself; busybox gets sorted here instead
+fn metrics_script(top: usize) -> String {
+    format!(
+        "{counters}; cat /proc/loadavg; echo {mark}; cat /proc/meminfo; echo {mark}; \
+         if df -kP -x tmpfs / >/dev/null 2>&1; \
+         then df -kP -x tmpfs -x devtmpfs -x squashfs -x overlay 2>/dev/null; \
+         else df -kP 2>/dev/null; fi; echo {mark}; \
+         if ps -eo pid --sort=-pcpu >/dev/null 2>&1; \
+         then ps -eo pid,user,pcpu,pmem,rss,comm --sort=-pcpu | head -n {lines}; \
+         else ps -eo pid,user,pcpu,pmem,rss,comm; fi; \
+         echo {mark}; nproc; cat /proc/uptime",
+        counters = counter_script(),
+        mark = SECTION_MARK,
+        lines = top + 1
+    )
+}
+
+fn parse_counters(stat: &str, net_dev: &str) -> Counters {
+    let mut counters = Counters {
+        taken_at: Some(Instant::now()),
+        ..Counters::default()
+    };
+    if let Some(line) = stat.lines().find(|line| line.starts_with("cpu ")) {
+        // user nice system idle iowait irq softirq steal
+        let values: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .take(8)
+            .filter_map(|value| value.parse().ok())
+            .collect();
+        counters.cpu_total = values.iter().sum();
+        counters.cpu_idle =
+            values.get(3).copied().unwrap_or(0) + values.get(4).copied().unwrap_or(0);
+    }
+    for line in net_dev.lines().skip(2) {
+        let Some((name, data)) = line.split_once(':') else {
+            continue;
+        };
+        let fields: Vec<u64> = data
+            .split_whitespace()
+            .filter_map(|value| value.parse().ok())
+            .collect();
+        if fields.len() >= 9 {
+            counters
+                .network
+                .insert(name.trim().to_string(), (fields[0], fields[8]));
+        }
+    }
+    counters
+}
+
+fn parse_memory(meminfo: &str) -> (MemoryMetrics, SwapMetrics) {
+    let values: HashMap<&str, u64> = meminfo
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(':')?;
+            let kb: u64 = rest.split_whitespace().next()?.parse().ok()?;
+            Some((name, kb * 1024))
+        })
+        .collect();
+    let get = |name: &str| values.get(name).copied().unwrap_or(0);
+    let total = get("MemTotal");
+    let free = get("MemFree");
+    let buffers = get("Buffers");
+    let cached = get("Cached") + get("SReclaimable");
+    // kernels before 3.14 have no MemAvailable
+    let available = values
+        .get("MemAvailable")
+        .copied()
+        .unwrap_or(free + buffers + cached);
+    let swap_total = get("SwapTotal");
+    let swap_free = get("SwapFree");
+    (
+        MemoryMetrics {
+            total,
+            used: total.saturating_sub(available),
+            free,
+            available,
+            buffers,
+            cached,
+        },
+        SwapMetrics {
+            total: swap_total,
+            used: swap_total.saturating_sub(swap_free),
+            free: swap_free,
+        },
+    )
+}
+
+fn parse_disks(df: &str) -> Vec<DiskMetrics> {
+    df.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let total: u64 = fields[1].parse().ok()?;
+            let used: u64 = fields[2].parse().ok()?;
+            let available: u64 = fields[3].parse().ok()?;
+            Some(DiskMetrics {
+                filesystem: fields[0].to_string(),
+                // mount points may contain spaces
+                mount: fields[5..].join(" "),
+                total: total * 1024,
+                used: used * 1024,
+                available: available * 1024,
+                usage: if used + available > 0 {
+                    used as f64 * 100.0 / (used + available) as f64
+                } else {
+                    0.0
+                },
+            })
+        })
+        .collect()
+}
+
+fn parse_processes(ps: &str, top: usize) -> Vec<ProcessMetrics> {
+    let mut processes: Vec<ProcessMetrics> = ps
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            Some(ProcessMetrics {
+                pid: fields[0].parse().ok()?,
+                user: fields[1].to_string(),
+                cpu: fields[2].parse().unwrap_or(0.0),
+                memory: fields[3].parse().unwrap_or(0.0),
+                rss: fields[4].parse::<u64>().unwrap_or(0) * 1024,
+                command: fields[5..].join(" "),
+            })
+        })
+        .collect();
+    processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+    processes.truncate(top);
+    processes
+}
+
+fn parse_load(loadavg: &str) -> LoadAverage {
+    let values: Vec<f64> = loadavg
+        .split_whitespace()
+        .take(3)
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    LoadAverage {
+        one: values.first().copied().unwrap_or(0.0),
+        five: values.get(1).copied().unwrap_or(0.0),
+        fifteen: values.get(2).copied().unwrap_or(0.0),
+    }
+}
+
+// without a previous sample the cpu usage is the average since boot and
+// network rates are zero
+fn build_metrics(
+    sections: &[&str],
+    previous: Option<&Counters>,
+    top: usize,
+) -> Result<(HostMetrics, Counters), String> {
+    if sections.len() < 7 {
+        return Err(String::from(
+            "unexpected metrics output, is this a linux host?",
+        ));
+    }
+    let counters = parse_counters(sections[0], sections[1]);
+    let (memory, swap) = parse_memory(sections[3]);
+    let mut system = sections[6].lines();
+    let cores = system
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .unwrap_or(0);
+    let uptime = system
+        .next()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|value| value.parse::<f64>().ok())
+        .unwrap_or(0.0) as u64;
+
+    let base = previous.cloned().unwrap_or_default();
+    let total = counters.cpu_total.saturating_sub(base.cpu_total);
+    let idle = counters.cpu_idle.saturating_sub(base.cpu_idle);
+    let usage = if total > 0 {
+        (total - idle.min(total)) as f64 * 100.0 / total as f64
+    } else {
+        0.0
+    };
+    let elapsed = match (base.taken_at, counters.taken_at) {
+        (Some(start), Some(end)) => end.duration_since(start).as_secs_f64(),
+        _ => 0.0,
+    };
+    let rate = |now: u64, before: Option<u64>| match before {
+        Some(before) if elapsed > 0.0 => now.saturating_sub(before) as f64 / elapsed,
+        _ => 0.0,
+    };
+    let mut network: Vec<NetworkMetrics> = counters
+        .network
+        .iter()
+        .map(|(name, (rx, tx))| {
+            let before = base.network.get(name);
+            NetworkMetrics {
+                interface: name.clone(),
+                rx_bytes: *rx,
+                tx_bytes: *tx,
+                rx_rate: rate(*rx, before.map(|b| b.0)),
+                tx_rate: rate(*tx, before.map(|b| b.1)),
+            }
+        })
+        .collect();
+    network.sort_by(|a, b| a.interface.cmp(&b.interface));
+
+    let metrics = HostMetrics {
+        timestamp: timestamp(),
+        uptime,
+        cpu: CpuMetrics { usage, cores },
+        load: parse_load(sections[2]),
+        memory,
+        swap,
+        disks: parse_disks(sections[4]),
+        network,
+        processes: parse_processes(sections[5], top),
+    };
+    Ok((metrics, counters))
+}
+
+fn run_script(session: &Session, script: &str) -> Result<String, String> {
+    let output = exec_collect(session, script, Some(COMMAND_TIMEOUT))?;
+    if output.stdout.trim().is_empty() {
+        return Err(format!("collect metrics error:{}", output.stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn split_sections(output: &str) -> Vec<&str> {
+    output.split(&format!("{}\n", SECTION_MARK)).collect()
+}
+
+fn sample(
+    session: &Session,
+    previous: Option<&Counters>,
+    top: usize,
+) -> Result<(HostMetrics, Counters), String> {
+    let output = run_script(session, &metrics_script(top))?;
+    build_metrics(&split_sections(&output), previous, top)
+}
+
+// takes a baseline of the counters first so that cpu usage and network
+// rates cover the last `window` seconds
+fn snapshot(session: &Session, window: u64, top: usize) -> Result<HostMetrics, String> {
+    let output = run_script(session, &counter_script())?;
+    let sections = split_sections(&output);
+    let baseline = parse_counters(sections[0], sections.get(1).copied().unwrap_or(""));
+    thread::sleep(Duration::from_secs(window));
+    sample(session, Some(&baseline), top).map(|(metrics, _)| metrics)
+}
+
+#[tauri::command]
+pub async fn get_host_metrics(
+    session_key: String,
+    window: Option<u64>,
+    top: Option<usize>,
+) -> Result<HostMetrics, String> {
+    let session = get_session(&session_key)?;
+    let window = window.unwrap_or(1).max(1);
+    let top = top.unwrap_or(DEFAULT_TOP_PROCESSES);
+    tokio::task::spawn_blocking(move || snapshot(&session, window, top))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn run_monitor(
+    app: AppHandle,
+    monitor_id: String,
+    session_key: String,
+    interval: Duration,
+    top: usize,
+    stop: Arc<AtomicBool>,
+) {
+    let mut previous: Option<Counters> = None;
+    while !stop.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        let result =
+            get_session(&session_key).and_then(|session| sample(&session, previous.as_ref(), top));
+        let (metrics, error) = match result {
+            Ok((metrics, counters)) => {
+                previous = Some(counters);
+                (Some(metrics), None)
+            }
+            Err(err) => (None, Some(err)),
+        };
+        let _ = app.emit(
+            METRICS_EVENT,
+            MetricsSample {
+                monitor_id: monitor_id.clone(),
+                session_key: session_key.clone(),
+                metrics,
+                error,
+            },
+        );
+        // sleep in short steps so stopping does not wait a whole interval
+        while started.elapsed() < interval && !stop.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    if let Ok(mut list) = MONITOR_MAP.lock() {
+        list.remove(&monitor_id);
+    }
+}
+
+// emits a "ssh_host_metrics" event every `interval` seconds until stopped
+#[tauri::command]
+pub async fn start_host_metrics(
+    app: AppHandle,
+    session_key: String,
+    interval: Option<u64>,
+    top: Option<usize>,
+) -> Result<String, String> {
+    get_session(&session_key)?;
+    let interval = Duration::from_secs(interval.unwrap_or(5).max(MIN_POLL_INTERVAL));
+    let top = top.unwrap_or(DEFAULT_TOP_PROCESSES);
+    let monitor_id = Uuid::new_v4().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    MONITOR_MAP
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(monitor_id.clone(), stop.clone());
+    let id = monitor_id.clone();
+    thread::spawn(move || run_monitor(app, id, session_key, interval, top, stop));
+    Ok(monitor_id)
+}
+
+#[tauri::command]
+pub async fn stop_host_metrics(monitor_id: String) -> Result<(), String> {
+    let list = MONITOR_MAP.lock().map_err(|e| e.to_string())?;
+    let stop = list.get(&monitor_id).ok_or("no metrics monitor")?;
+    stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: &str = "cpu  4705 356 584 3699 23 0 23 0 0 0\n";
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    8160      96    0    0    0     0          0         0     8160      96    0    0    0     0       0          0
+  eth0: 1843716    2345    0    0    0     0          0         0   284410    1712    0    0    0     0       0          0
+";
+
+    #[test]
+    fn test_parse_counters() {
+        let counters = parse_counters(STAT, NET_DEV);
+        assert_eq!(counters.cpu_total, 4705 + 356 + 584 + 3699 + 23 + 23);
+        assert_eq!(counters.cpu_idle, 3699 + 23);
+        assert_eq!(counters.network.get("eth0"), Some(&(1843716, 284410)));
+        assert_eq!(counters.network.get("lo"), Some(&(8160, 8160)));
+        assert_eq!(counters.network.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        let meminfo = "\
+MemTotal:        8048576 kB
+MemFree:          512000 kB
+MemAvailable:    4024288 kB
+Buffers:          102400 kB
+Cached:          2048000 kB
+SReclaimable:     204800 kB
+SwapTotal:       2097148 kB
+SwapFree:        1048574 kB
+";
+        let (memory, swap) = parse_memory(meminfo);
+        assert_eq!(memory.total, 8048576 * 1024);
+        assert_eq!(memory.available, 4024288 * 1024);
+        assert_eq!(memory.used, (8048576 - 4024288) * 1024);
+        assert_eq!(memory.cached, (2048000 + 204800) * 1024);
+        assert_eq!(swap.used, (2097148 - 1048574) * 1024);
+
+        // old kernels: available is estimated from free, buffers and cache
+        let (memory, _) =
+            parse_memory("MemTotal: 1000 kB\nMemFree: 100 kB\nBuffers: 50 kB\nCached: 250 kB\n");
+        assert_eq!(memory.available, 400 * 1024);
+        assert_eq!(memory.used, 600 * 1024);
+    }
+
+    #[test]
+    fn test_parse_disks() {
+        let df = "\
+Filesystem     1024-blocks     Used Available Capacity Mounted on
+/dev/sda1         41152736 12345678  26693914      32% /
+/dev/sdb1          1000000   250000    750000      25% /mnt/backup disk
+broken line
+";
+        let disks = parse_disks(df);
+        assert_eq!(disks.len(), 2);
+        assert_eq!(disks[0].filesystem, "/dev/sda1");
+        assert_eq!(disks[0].mount, "/");
+        assert_eq!(disks[0].total, 41152736 * 1024);
+        assert_eq!(disks[1].mount, "/mnt/backup disk");
+        assert_eq!(disks[1].usage, 25.0);
+    }
+
+    #[test]
+    fn test_parse_processes() {
+        let ps = "\
+  PID USER     %CPU %MEM   RSS COMMAND
+    1 root      0.0  0.1 11840 systemd
+  812 mysql     1.5 10.2 835000 mysqld
+ 2231 www-data 12.5  2.0 164000 php-fpm: pool www
+";
+        let processes = parse_processes(ps, 2);
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[0].pid, 2231);
+        assert_eq!(processes[0].command, "php-fpm: pool www");
+        assert_eq!(processes[0].rss, 164000 * 1024);
+        assert_eq!(processes[1].user, "mysql");
+    }
+
+    fn sections(stat: &str, net_dev: &str) -> Vec<String> {
+        vec![
+            stat.to_string(),
+            net_dev.to_string(),
+            String::from("0.52 0.58 0.59 1/389 12345\n"),
+            String::from("MemTotal: 1000 kB\nMemAvailable: 500 kB\n"),
+            String::from("Filesystem 1024-blocks Used Available Capacity Mounted on\n"),
+            String::from("PID USER %CPU %MEM RSS COMMAND\n"),
+            String::from("4\n12345.67 45678.90\n"),
+        ]
+    }
+
+    #[test]
+    fn test_build_metrics_rates() {
+        let owned = sections(STAT, NET_DEV);
+        let sections: Vec<&str> = owned.iter().map(String::as_str).collect();
+        let previous = Counters {
+            cpu_total: 9390 - 1000,
+            cpu_idle: 3722 - 250,
+            network: HashMap::from([(String::from("eth0"), (1843716 - 2000, 284410 - 1000))]),
+            taken_at: Instant::now().checked_sub(Duration::from_secs(1)),
+        };
+        let (metrics, _) = build_metrics(&sections, Some(&previous), 10).unwrap();
+        assert_eq!(metrics.cpu.cores, 4);
+        assert_eq!(metrics.uptime, 12345);
+        assert_eq!(metrics.load.five, 0.58);
+        assert!((metrics.cpu.usage - 75.0).abs() < 0.01);
+        let eth0 = metrics
+            .network
+            .iter()
+            .find(|n| n.interface == "eth0")
+            .unwrap();
+        assert!(eth0.rx_rate > 1500.0 && eth0.rx_rate <= 2000.0);
+        // an interface missing from the previous sample has no rate yet
+        let lo = metrics
+            .network
+            .iter()
+            .find(|n| n.interface == "lo")
+            .unwrap();
+        assert_eq!(lo.rx_rate, 0.0);
+    }
+
+    #[test]
+    fn test_build_metrics_counter_reset() {
+        let owned = sections(STAT, NET_DEV);
+        let sections: Vec<&str> = owned.iter().map(String::as_str).collect();
+        // counters above the current ones: a reboot, a wrapped 32 bit
+        // counter or a recreated interface
+        let previous = Counters {
+            cpu_total: u64::MAX,
+            cpu_idle: u64::MAX,
+            network: HashMap::from([(String::from("eth0"), (u32::MAX as u64, u32::MAX as u64))]),
+            taken_at: Instant::now().checked_sub(Duration::from_secs(1)),
+        };
+        let (metrics, _) = build_metrics(&sections, Some(&previous), 10).unwrap();
+        assert_eq!(metrics.cpu.usage, 0.0);
+        let eth0 = metrics
+            .network
+            .iter()
+            .find(|n| n.interface == "eth0")
+            .unwrap();
+        assert_eq!(eth0.rx_rate, 0.0);
+        assert_eq!(eth0.tx_rate, 0.0);
+    }
+
+    #[test]
+    fn test_build_metrics_short_output() {
+        assert!(build_metrics(&["cpu 1 2 3 4\n", ""], None, 10).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use super::ssh::{connect_ssh_session, store_session, SshConnectConfig, SshJumpHost};
+use super::ssh_auth::SshAuth;
+use crate::toolbox::ssh_config::{expand_tilde, parse_jump, SshConfig, SshHostProfile};
+use std::env;
+use std::path::Path;
+use tauri::AppHandle;
+
+// what ssh falls back to when a host sets no IdentityFile
+const DEFAULT_IDENTITY_FILES: [&str; 3] = ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+fn load_config(path: Option<String>) -> Result<SshConfig, String> {
+    let path = match path {
+        Some(path) if !path.is_empty() => expand_tilde(&path),
+        _ => SshConfig::default_path()?,
+    };
+    SshConfig::load(&path)
+}
+
+fn local_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+// without explicit credentials the first IdentityFile on disk is used, or the
+// first of ssh's default keys when none is configured, then the ssh agent
+fn profile_auth(profile: &SshHostProfile) -> SshAuth {
+    let candidates = match profile.identity_files.is_empty() {
+        true => DEFAULT_IDENTITY_FILES
+            .iter()
+            .map(|file| expand_tilde(file).to_string_lossy().to_string())
+            .collect(),
+        false => profile.identity_files.clone(),
+    };
+    match candidates
+        .into_iter()
+        .find(|file| Path::new(file).is_file())
+    {
+        Some(path) => SshAuth::KeyFile {
+            path,
+            passphrase: None,
+        },
+        None => SshAuth::Agent,
+    }
+}
+
+// ProxyJump hops are themselves looked up in the config, with any user or
+// port written in the hop taking precedence
+fn jump_hosts(config: &SshConfig, profile: &SshHostProfile) -> Vec<SshJumpHost> {
+    profile
+        .proxy_jump
+        .iter()
+        .map(|hop| {
+            let (user, alias, port) = parse_jump(hop);
+            let jump = config.resolve(&alias);
+            SshJumpHost {
+                host: jump.host_name.clone(),
+                port: port.unwrap_or(jump.port),
+                user: user
+                    .or_else(|| jump.user.clone())
+                    .unwrap_or_else(local_user),
+                auth: profile_auth(&jump),
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub async fn get_ssh_config_file() -> Result<String, String> {
+    Ok(SshConfig::default_path()?.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn list_ssh_config_profiles(path: Option<String>) -> Result<Vec<SshHostProfile>, String> {
+    let config = load_config(path)?;
+    Ok(config
+        .aliases()
+        .iter()
+        .map(|alias| config.resolve(alias))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn ssh_connect_by_alias(
+    app: AppHandle,
+    alias: String,
+    auth: Option<SshAuth>,
+    path: Option<String>,
+    key: Option<String>,
+) -> Result<String, String> {
+    let config = load_config(path)?;
+    let profile = config.resolve(&alias);
+    let connect_config = SshConnectConfig {
+        host: profile.host_name.clone(),
+        port: profile.port,
+        user: profile.user.clone().unwrap_or_else(local_user),
+        auth: auth.unwrap_or_else(|| profile_auth(&profile)),
+        jump_hosts: jump_hosts(&config, &profile),
+        keepalive_interval: 0,
+        auto_reconnect: false,
+    };
+    let (session, connect_config, app) = tokio::task::spawn_blocking(move || {
+        connect_ssh_session(&connect_config, Some(&app))
+            .map(|session| (session, connect_config, app))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    store_session(key, session, &connect_config, Some(&app))
+}
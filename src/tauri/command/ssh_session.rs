@@ -0,0 +1,397 @@
+use super::ssh::{
+    attempt, connect_ssh_session, forget_call_lock, is_retryable, retry_for, share_call_lock,
+    SessionHandle, SshConnectConfig, SESSION_MAP,
+};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use ssh2::Session;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+const KEEPALIVE_TICK: Duration = Duration::from_secs(1);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// seconds between reconnect attempts, doubled after each failure
+const RECONNECT_DELAY_MIN: u64 = 2;
+const RECONNECT_DELAY_MAX: u64 = 60;
+const SESSION_STATUS_EVENT: &str = "ssh_session_status";
+
+struct SessionMeta {
+    config: SshConnectConfig,
+    // kept so keyboard-interactive prompts still reach the frontend on reconnect
+    app: Option<AppHandle>,
+    keepalive_interval: u32,
+    auto_reconnect: bool,
+    connected_at: u64,
+    last_used: u64,
+    banner: Option<String>,
+    alive: bool,
+    reconnect_count: u32,
+    message: String,
+    reconnecting: bool,
+    reconnect_delay: u64,
+    next_reconnect: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionInfo {
+    session_key: String,
+    host: String,
+    port: u16,
+    user: String,
+    connected_at: u64,
+    last_used: u64,
+    banner: Option<String>,
+    keepalive_interval: u32,
+    auto_reconnect: bool,
+    alive: bool,
+    reconnect_count: u32,
+    message: String,
+}
+
+// session_key is empty for a jump host, which has no key of its own
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStatus {
+    session_key: String,
+    host: String,
+    port: u16,
+    alive: bool,
+    message: String,
+}
+
+lazy_static! {
+    static ref SESSION_INFO: Arc<Mutex<HashMap<String, SessionMeta>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+static KEEPALIVE_WORKER: Once = Once::new();
+
+impl SessionMeta {
+    fn info(&self, session_key: &str) -> SessionInfo {
+        SessionInfo {
+            session_key: session_key.to_string(),
+            host: self.config.host.clone(),
+            port: self.config.port,
+            user: self.config.user.clone(),
+            connected_at: self.connected_at,
+            last_used: self.last_used,
+            banner: self.banner.clone(),
+            keepalive_interval: self.keepalive_interval,
+            auto_reconnect: self.auto_reconnect,
+            alive: self.alive,
+            reconnect_count: self.reconnect_count,
+            message: self.message.clone(),
+        }
+    }
+
+    fn emit_status(&self, session_key: &str) {
+        if let Some(app) = &self.app {
+            emit_session_status(
+                app,
+                session_key,
+                &self.config.host,
+                self.config.port,
+                self.alive,
+                self.message.clone(),
+            );
+        }
+    }
+}
+
+pub fn emit_session_status(
+    app: &AppHandle,
+    session_key: &str,
+    host: &str,
+    port: u16,
+    alive: bool,
+    message: String,
+) {
+    let _ = app.emit(
+        SESSION_STATUS_EVENT,
+        SessionStatus {
+            session_key: session_key.to_string(),
+            host: host.to_string(),
+            port,
+            alive,
+            message,
+        },
+    );
+}
+
+fn apply_keepalive(session: &Session, interval: u32) {
+    // an interval of 0 turns keepalive off in libssh2
+    session.set_keepalive(true, interval);
+}
+
+pub fn record_session(
+    session_key: &str,
+    session: &Session,
+    config: &SshConnectConfig,
+    app: Option<&AppHandle>,
+) {
+    apply_keepalive(session, config.keepalive_interval);
+    let now = timestamp();
+    if let Ok(mut list) = SESSION_INFO.lock() {
+        list.insert(
+            session_key.to_string(),
+            SessionMeta {
+                config: config.clone(),
+                app: app.cloned(),
+                keepalive_interval: config.keepalive_interval,
+                auto_reconnect: config.auto_reconnect,
+                connected_at: now,
+                last_used: now,
+                banner: session.banner().map(String::from),
+                alive: true,
+                reconnect_count: 0,
+                message: String::new(),
+                reconnecting: false,
+                reconnect_delay: RECONNECT_DELAY_MIN,
+                next_reconnect: 0,
+            },
+        );
+    }
+    KEEPALIVE_WORKER.call_once(|| {
+        thread::spawn(run_keepalive);
+    });
+}
+
+pub fn touch_session(session_key: &str) {
+    if let Ok(mut list) = SESSION_INFO.lock() {
+        if let Some(meta) = list.get_mut(session_key) {
+            meta.last_used = timestamp();
+        }
+    }
+}
+
+pub fn forget_session(session_key: &str) {
+    if let Ok(mut list) = SESSION_INFO.lock() {
+        list.remove(session_key);
+    }
+}
+
+fn mark_dead(session_key: &str, message: String) {
+    if let Ok(mut list) = SESSION_INFO.lock() {
+        if let Some(meta) = list.get_mut(session_key) {
+            meta.alive = false;
+            meta.message = message;
+            meta.emit_status(session_key);
+        }
+    }
+}
+
+// opening a channel needs a round trip, unlike keepalive_send which only
+// queues a packet on a socket that may already be dead
+fn probe_session(session: &Session) -> Result<(), String> {
+    retry_for(session, PROBE_TIMEOUT, || session.channel_session())
+        .map(|channel| SessionHandle::new(session, channel))
+        .and_then(|mut channel| retry_for(session, PROBE_TIMEOUT, || channel.close()))
+        .map_err(|e| format!("probe session error:{}", e))
+}
+
+// reconnects with the stored credentials and swaps the new session in under
+// the same key; channels opened on the old session are lost
+fn reconnect_session(session_key: &str) -> Result<Session, String> {
+    let (config, app) = {
+        let mut list = SESSION_INFO.lock().map_err(|e| e.to_string())?;
+        let meta = list.get_mut(session_key).ok_or("no session")?;
+        if meta.reconnecting {
+            return Err(String::from("reconnect in progress"));
+        }
+        meta.reconnecting = true;
+        (meta.config.clone(), meta.app.clone())
+    };
+    let session = match connect_ssh_session(&config, app.as_ref()) {
+        Ok(session) => session,
+        Err(err) => {
+            // the next try backs off; the keepalive worker keeps trying for
+            // auto reconnect sessions until they are disconnected
+            if let Ok(mut list) = SESSION_INFO.lock() {
+                if let Some(meta) = list.get_mut(session_key) {
+                    meta.alive = false;
+                    meta.message = format!("reconnect error:{}", err);
+                    meta.reconnecting = false;
+                    meta.next_reconnect = timestamp() + meta.reconnect_delay;
+                    meta.reconnect_delay = (meta.reconnect_delay * 2).min(RECONNECT_DELAY_MAX);
+                    meta.emit_status(session_key);
+                }
+            }
+            return Err(err);
+        }
+    };
+    let mut list = SESSION_INFO.lock().map_err(|e| e.to_string())?;
+    // disconnected while the new session was being set up
+    let Some(meta) = list.get_mut(session_key) else {
+        let _ = attempt(&session, || session.disconnect(None, "disconnected", None));
+        return Err(String::from("no session"));
+    };
+    apply_keepalive(&session, meta.keepalive_interval);
+    let now = timestamp();
+    meta.connected_at = now;
+    meta.last_used = now;
+    meta.banner = session.banner().map(String::from);
+    meta.alive = true;
+    meta.reconnect_count += 1;
+    meta.message = String::new();
+    meta.reconnecting = false;
+    meta.reconnect_delay = RECONNECT_DELAY_MIN;
+    meta.emit_status(session_key);
+    drop(list);
+    share_call_lock(&session);
+    let old = SESSION_MAP
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(session_key.to_string(), session.clone());
+    if let Some(old) = old {
+        // a session that would not disconnect may still be in use and keeps
+        // its lock
+        if attempt(&old, || old.disconnect(None, "reconnected", None)).is_ok() {
+            forget_call_lock(&old);
+        }
+    }
+    Ok(session)
+}
+
+fn handle_dead_session(session_key: &str, message: String) {
+    let auto_reconnect = SESSION_INFO
+        .lock()
+        .ok()
+        .and_then(|list| list.get(session_key).map(|meta| meta.auto_reconnect))
+        .unwrap_or(false);
+    mark_dead(session_key, message);
+    if auto_reconnect {
+        let _ = reconnect_session(session_key);
+    }
+}
+
+// dead auto reconnect sessions whose back-off has run out
+fn reconnect_due() -> Vec<String> {
+    let now = timestamp();
+    match SESSION_INFO.lock() {
+        Ok(list) => list
+            .iter()
+            .filter(|(_, meta)| {
+                meta.auto_reconnect
+                    && !meta.alive
+                    && !meta.reconnecting
+                    && meta.next_reconnect <= now
+            })
+            .map(|(key, _)| key.clone())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn run_keepalive() {
+    loop {
+        thread::sleep(KEEPALIVE_TICK);
+        for session_key in reconnect_due() {
+            thread::spawn(move || reconnect_session(&session_key));
+        }
+        let due: Vec<String> = match SESSION_INFO.lock() {
+            Ok(list) => list
+                .iter()
+                .filter(|(_, meta)| meta.keepalive_interval > 0 && meta.alive)
+                .map(|(key, _)| key.clone())
+                .collect(),
+            Err(_) => continue,
+        };
+        for session_key in due {
+            let session = match SESSION_MAP.lock() {
+                Ok(list) => list.get(&session_key).cloned(),
+                Err(_) => None,
+            };
+            let Some(session) = session else {
+                continue;
+            };
+            // libssh2 only sends once the interval has passed
+            let result =
+                attempt(&session, || session.keepalive_send()).map_err(std::io::Error::from);
+            match result {
+                Ok(_) => {}
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => handle_dead_session(&session_key, format!("keepalive error:{}", err)),
+            }
+        }
+    }
+}
+
+fn session_info(session_key: &str) -> Result<SessionInfo, String> {
+    let list = SESSION_INFO.lock().map_err(|e| e.to_string())?;
+    list.get(session_key)
+        .map(|meta| meta.info(session_key))
+        .ok_or_else(|| String::from("no session"))
+}
+
+#[tauri::command]
+pub async fn get_ssh_session_info(session_key: String) -> Result<SessionInfo, String> {
+    session_info(&session_key)
+}
+
+#[tauri::command]
+pub async fn list_ssh_sessions() -> Result<Vec<SessionInfo>, String> {
+    let list = SESSION_INFO.lock().map_err(|e| e.to_string())?;
+    Ok(list.iter().map(|(key, meta)| meta.info(key)).collect())
+}
+
+#[tauri::command]
+pub async fn set_ssh_keepalive(
+    session_key: String,
+    interval: u32,
+    auto_reconnect: Option<bool>,
+) -> Result<SessionInfo, String> {
+    let session = SESSION_MAP
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&session_key)
+        .cloned()
+        .ok_or("no session")?;
+    apply_keepalive(&session, interval);
+    let mut list = SESSION_INFO.lock().map_err(|e| e.to_string())?;
+    let meta = list.get_mut(&session_key).ok_or("no session")?;
+    meta.keepalive_interval = interval;
+    if let Some(auto_reconnect) = auto_reconnect {
+        meta.auto_reconnect = auto_reconnect;
+    }
+    Ok(meta.info(&session_key))
+}
+
+// probes the session with a real round trip; a dead session is reconnected
+// when auto reconnect is on
+#[tauri::command]
+pub async fn check_ssh_session(session_key: String) -> Result<SessionInfo, String> {
+    tokio::task::spawn_blocking(move || {
+        let session = SESSION_MAP
+            .lock()
+            .map_err(|e| e.to_string())?
+            .get(&session_key)
+            .cloned()
+            .ok_or("no session")?;
+        match probe_session(&session) {
+            Ok(_) => {
+                if let Ok(mut list) = SESSION_INFO.lock() {
+                    if let Some(meta) = list.get_mut(&session_key) {
+                        meta.alive = true;
+                        meta.message = String::new();
+                    }
+                }
+            }
+            Err(err) => handle_dead_session(&session_key, err),
+        }
+        session_info(&session_key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn reconnect_ssh_session(session_key: String) -> Result<SessionInfo, String> {
+    tokio::task::spawn_blocking(move || {
+        reconnect_session(&session_key)?;
+        session_info(&session_key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,332 @@
+use super::ssh::{get_session, SftpClient};
+use super::ssh_transfer::{
+    download_entry, new_dir_transfer_info, start_transfer, transfer_filter, upload_entry,
+    TransferContext, TransferInfo, DIRECTION_DOWNLOAD, DIRECTION_UPLOAD, KIND_SYNC,
+};
+use super::tree::{
+    join_remote, remote_sums, set_local_mode, set_local_times, sftp_create_dir_all, times_stat,
+    walk_local, walk_remote, TreeEntry,
+};
+use crate::toolbox::file::{file_hash, PathFilter};
+use serde::{Deserialize, Serialize};
+use ssh2::Session;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const COMPARE_SIZE_MTIME: &str = "size_mtime";
+const COMPARE_HASH: &str = "hash";
+
+const ACTION_MKDIR: &str = "mkdir";
+const ACTION_COPY: &str = "copy";
+const ACTION_DELETE: &str = "delete";
+const ACTION_CONFLICT: &str = "conflict";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SyncOptions {
+    compare: Option<String>,
+    delete: Option<bool>,
+    dry_run: Option<bool>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SyncAction {
+    action: String,
+    path: String,
+    reason: String,
+    is_dir: bool,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SyncResult {
+    transfer_id: Option<String>,
+    actions: Vec<SyncAction>,
+}
+
+fn action(action: &str, entry: &TreeEntry, reason: &str) -> SyncAction {
+    SyncAction {
+        action: action.to_string(),
+        path: entry.relative.clone(),
+        reason: reason.to_string(),
+        is_dir: entry.is_dir,
+        size: if entry.is_dir { 0 } else { entry.size },
+    }
+}
+
+// an absent root is an empty tree, so a first sync copies everything
+fn walk_side(
+    sftp: &SftpClient,
+    remote: bool,
+    root: &str,
+    filter: &PathFilter,
+) -> Result<Vec<TreeEntry>, String> {
+    let mut entries = Vec::new();
+    if remote {
+        if sftp.call(|s| s.stat(Path::new(root))).is_ok() {
+            walk_remote(sftp, root, "", filter, &mut entries)?;
+        }
+    } else if Path::new(root).is_dir() {
+        walk_local(Path::new(root), "", filter, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+fn side_hashes(
+    session: &Session,
+    remote: bool,
+    root: &str,
+    paths: &[&str],
+) -> Result<HashMap<String, String>, String> {
+    if remote {
+        return remote_sums(session, "sha256", root, paths);
+    }
+    let mut hashes = HashMap::new();
+    for path in paths {
+        let hash = file_hash(&Path::new(root).join(path), "sha256")?;
+        hashes.insert(path.to_string(), hash);
+    }
+    Ok(hashes)
+}
+
+// source and target roots for a sync, and which of them is remote
+fn sides(info: &TransferInfo) -> (&str, bool, &str, bool) {
+    if info.direction == DIRECTION_UPLOAD {
+        (&info.local_file, false, &info.remote_file, true)
+    } else {
+        (&info.remote_file, true, &info.local_file, false)
+    }
+}
+
+// compares both trees and lists what a sync would do: directories to create,
+// files to copy (new, different size, different mtime or hash) and, with
+// `delete`, target entries missing from the source, deepest first
+fn plan_sync(
+    session: &Session,
+    sftp: &SftpClient,
+    info: &TransferInfo,
+) -> Result<(Vec<SyncAction>, HashMap<String, TreeEntry>), String> {
+    let filter = transfer_filter(info)?;
+    let (source_root, source_remote, target_root, target_remote) = sides(info);
+    let source = walk_side(sftp, source_remote, source_root, &filter)?;
+    let target = walk_side(sftp, target_remote, target_root, &filter)?;
+    let target_map: HashMap<&str, &TreeEntry> = target
+        .iter()
+        .map(|entry| (entry.relative.as_str(), entry))
+        .collect();
+
+    let mut actions = Vec::new();
+    let mut same_size = Vec::new();
+    for entry in source.iter() {
+        match target_map.get(entry.relative.as_str()) {
+            None if entry.is_dir => actions.push(action(ACTION_MKDIR, entry, "new")),
+            None => actions.push(action(ACTION_COPY, entry, "new")),
+            Some(existing) if existing.is_dir != entry.is_dir => {
+                actions.push(action(ACTION_CONFLICT, entry, "type"))
+            }
+            Some(_) if entry.is_dir => {}
+            Some(existing) if existing.size != entry.size => {
+                actions.push(action(ACTION_COPY, entry, "size"))
+            }
+            Some(existing) => {
+                if info.compare == COMPARE_HASH {
+                    same_size.push(entry);
+                } else if existing.mtime != entry.mtime {
+                    actions.push(action(ACTION_COPY, entry, "mtime"));
+                }
+            }
+        }
+    }
+    if !same_size.is_empty() {
+        let paths: Vec<&str> = same_size.iter().map(|e| e.relative.as_str()).collect();
+        let source_hashes = side_hashes(session, source_remote, source_root, &paths)?;
+        let target_hashes = side_hashes(session, target_remote, target_root, &paths)?;
+        for entry in same_size {
+            let source_hash = source_hashes.get(&entry.relative);
+            if source_hash.is_none() || source_hash != target_hashes.get(&entry.relative) {
+                actions.push(action(ACTION_COPY, entry, "hash"));
+            }
+        }
+    }
+    if info.delete {
+        let source_paths: HashSet<&str> =
+            source.iter().map(|entry| entry.relative.as_str()).collect();
+        for entry in target.iter().rev() {
+            if !source_paths.contains(entry.relative.as_str()) {
+                actions.push(action(ACTION_DELETE, entry, "extraneous"));
+            }
+        }
+    }
+    let source_map = source
+        .into_iter()
+        .map(|entry| (entry.relative.clone(), entry))
+        .collect();
+    Ok((actions, source_map))
+}
+
+// deletes come deepest first, so a directory only holds what the filter left
+// out by the time it is reached; such a directory is kept and false returned
+fn delete_target(
+    sftp: &SftpClient,
+    info: &TransferInfo,
+    item: &SyncAction,
+) -> Result<bool, String> {
+    let result = if info.direction == DIRECTION_UPLOAD {
+        let path = join_remote(&info.remote_file, &item.path);
+        let path = Path::new(&path);
+        match item.is_dir {
+            true if sftp
+                .call(|s| s.readdir(path))
+                .is_ok_and(|list| !list.is_empty()) =>
+            {
+                return Ok(false)
+            }
+            true => sftp.call(|s| s.rmdir(path)),
+            false => sftp.call(|s| s.unlink(path)),
+        }
+        .map_err(|e| e.to_string())
+    } else {
+        let path = Path::new(&info.local_file).join(&item.path);
+        match item.is_dir {
+            true if fs::read_dir(&path).is_ok_and(|mut list| list.next().is_some()) => {
+                return Ok(false)
+            }
+            true => fs::remove_dir(path),
+            false => fs::remove_file(path),
+        }
+        .map_err(|e| e.to_string())
+    };
+    result
+        .map(|_| true)
+        .map_err(|e| format!("delete {} error:{}", item.path, e))
+}
+
+pub fn run_sync(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let session = get_session(&info.session_key)?;
+    let sftp = SftpClient::open(&session)?;
+    let (actions, source) = plan_sync(&session, &sftp, info)?;
+    // a file on one side and a directory on the other needs a decision the
+    // sync cannot make, so nothing is changed until the user resolves it
+    let conflicts: Vec<&str> = actions
+        .iter()
+        .filter(|a| a.action == ACTION_CONFLICT)
+        .map(|a| a.path.as_str())
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(format!("sync conflict error:{}", conflicts.join(", ")));
+    }
+    let copies: Vec<&SyncAction> = actions.iter().filter(|a| a.action == ACTION_COPY).collect();
+    ctx.begin(copies.iter().map(|a| a.size).sum(), copies.len() as u64);
+
+    let upload = info.direction == DIRECTION_UPLOAD;
+    let local_root = Path::new(&info.local_file);
+    if upload {
+        sftp_create_dir_all(&sftp, &info.remote_file, 0o755)?;
+    } else {
+        fs::create_dir_all(local_root).map_err(|e| format!("create dir error:{}", e))?;
+    }
+    for item in actions.iter().filter(|a| a.action == ACTION_MKDIR) {
+        ctx.checkpoint()?;
+        if upload {
+            // the mode follows with the directory times, a read only source
+            // directory would refuse its children
+            sftp_create_dir_all(&sftp, &join_remote(&info.remote_file, &item.path), 0o755)?;
+        } else {
+            fs::create_dir_all(local_root.join(&item.path))
+                .map_err(|e| format!("create dir error:{}", e))?;
+        }
+    }
+    for item in copies {
+        ctx.checkpoint()?;
+        let entry = &source[&item.path];
+        if upload {
+            upload_entry(ctx, &sftp, local_root, &info.remote_file, entry)?;
+        } else {
+            download_entry(ctx, &sftp, local_root, &info.remote_file, entry)?;
+        }
+    }
+    let mut kept = Vec::new();
+    for item in actions.iter().filter(|a| a.action == ACTION_DELETE) {
+        ctx.checkpoint()?;
+        if !delete_target(&sftp, info, item)? {
+            kept.push(item.path.as_str());
+        }
+    }
+    if !kept.is_empty() {
+        ctx.note(format!(
+            "kept directories holding filtered out entries:{}",
+            kept.join(", ")
+        ));
+    }
+    // directory times last, deepest first, as in a directory transfer
+    let mut dirs: Vec<&TreeEntry> = source.values().filter(|entry| entry.is_dir).collect();
+    dirs.sort_by(|a, b| b.relative.cmp(&a.relative));
+    for entry in dirs {
+        if upload {
+            let remote = join_remote(&info.remote_file, &entry.relative);
+            let _ = sftp.call(|s| s.setstat(Path::new(&remote), times_stat(entry)));
+        } else {
+            let local_path = local_root.join(&entry.relative);
+            let _ = set_local_mode(&local_path, entry.mode);
+            let _ = set_local_times(&local_path, entry.atime, entry.mtime);
+        }
+    }
+    Ok(())
+}
+
+// one-way sync in `direction` (upload: local to remote, download: remote to
+// local); a dry run only returns the planned actions, otherwise the sync runs
+// in the transfer queue and its id is returned
+#[tauri::command]
+pub async fn sync_remote_dir(
+    session_key: String,
+    local_dir: String,
+    remote_dir: String,
+    direction: String,
+    options: Option<SyncOptions>,
+) -> Result<SyncResult, String> {
+    let session = get_session(&session_key)?;
+    if direction != DIRECTION_UPLOAD && direction != DIRECTION_DOWNLOAD {
+        return Err(format!("invalid direction:{}", direction));
+    }
+    let options = options.unwrap_or_default();
+    let compare = options
+        .compare
+        .unwrap_or_else(|| String::from(COMPARE_SIZE_MTIME));
+    if compare != COMPARE_SIZE_MTIME && compare != COMPARE_HASH {
+        return Err(format!("invalid compare mode:{}", compare));
+    }
+    if direction == DIRECTION_UPLOAD && !Path::new(&local_dir).is_dir() {
+        return Err(format!("{} is not a directory", local_dir));
+    }
+    let mut info = new_dir_transfer_info(
+        session_key,
+        &direction,
+        local_dir,
+        remote_dir,
+        options.include,
+        options.exclude,
+    )?;
+    info.kind = String::from(KIND_SYNC);
+    info.compare = compare;
+    info.delete = options.delete.unwrap_or(false);
+
+    if options.dry_run.unwrap_or(false) {
+        let actions = tokio::task::spawn_blocking(move || {
+            let sftp = SftpClient::open(&session)?;
+            plan_sync(&session, &sftp, &info).map(|(actions, _)| actions)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        return Ok(SyncResult {
+            transfer_id: None,
+            actions,
+        });
+    }
+    Ok(SyncResult {
+        transfer_id: Some(start_transfer(info)?),
+        actions: Vec::new(),
+    })
+}
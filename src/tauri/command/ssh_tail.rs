@@ -0,0 +1,288 @@
+use super::ssh::{get_session, SessionHandle};
+use super::ssh_exec::{finish_channel, open_killable_channel, pump_exec, shell_quote, ExecEnd};
+use crate::toolbox::time::timestamp;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, Session};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const TAIL_LINES_EVENT: &str = "ssh_tail_lines";
+const TAIL_EXIT_EVENT: &str = "ssh_tail_exit";
+const DEFAULT_BACKFILL: u32 = 10;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TailOptions {
+    // lines of existing content to send first
+    lines: Option<u32>,
+    // only lines matching this regex are sent
+    filter: Option<String>,
+    // every match of these regexes is reported as a highlight range
+    highlight: Option<Vec<String>>,
+    ignore_case: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TailInfo {
+    tail_id: String,
+    session_key: String,
+    files: Vec<String>,
+    started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TailLine {
+    file: String,
+    stream: String,
+    text: String,
+    // [start, end) in characters, one list per highlight pattern
+    highlights: Vec<Vec<(usize, usize)>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TailLines {
+    tail_id: String,
+    lines: Vec<TailLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TailExit {
+    tail_id: String,
+    message: String,
+}
+
+struct Tail {
+    info: TailInfo,
+    stop: Arc<AtomicBool>,
+}
+
+lazy_static! {
+    static ref TAIL_MAP: Arc<Mutex<HashMap<String, Tail>>> = Arc::new(Mutex::new(HashMap::new()));
+    static ref TAIL_HEADER: Regex = Regex::new(r"^==> (.*) <==$").unwrap();
+}
+
+struct LineMatcher {
+    filter: Option<Regex>,
+    highlight: Vec<Regex>,
+}
+
+fn build_regex(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| format!("invalid regex {}: {}", pattern, e))
+}
+
+impl LineMatcher {
+    fn new(options: &TailOptions) -> Result<LineMatcher, String> {
+        let ignore_case = options.ignore_case.unwrap_or(false);
+        let filter = match options.filter.as_deref() {
+            Some(pattern) if !pattern.is_empty() => Some(build_regex(pattern, ignore_case)?),
+            _ => None,
+        };
+        let mut highlight = Vec::new();
+        for pattern in options.highlight.iter().flatten() {
+            highlight.push(build_regex(pattern, ignore_case)?);
+        }
+        Ok(LineMatcher { filter, highlight })
+    }
+
+    fn accept(&self, text: &str) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.is_match(text))
+    }
+
+    // the frontend indexes by character, regex reports byte offsets
+    fn highlights(&self, text: &str) -> Vec<Vec<(usize, usize)>> {
+        let to_chars = |offset: usize| text[..offset].chars().count();
+        self.highlight
+            .iter()
+            .map(|regex| {
+                regex
+                    .find_iter(text)
+                    .filter(|found| !found.is_empty())
+                    .map(|found| (to_chars(found.start()), to_chars(found.end())))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// splits the tail output into lines and keeps track of which file they come
+// from; with several files tail separates them by "==> name <==" headers,
+// each preceded by a blank line
+struct LineReader {
+    multiple: bool,
+    current_file: String,
+    pending: [Vec<u8>; 2],
+    blank_held: bool,
+}
+
+impl LineReader {
+    fn push(
+        &mut self,
+        stream_id: usize,
+        data: &[u8],
+        matcher: &LineMatcher,
+        out: &mut Vec<TailLine>,
+    ) {
+        self.pending[stream_id].extend_from_slice(data);
+        while let Some(end) = self.pending[stream_id].iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.pending[stream_id].drain(..=end).collect();
+            let text = String::from_utf8_lossy(&raw[..end])
+                .trim_end_matches('\r')
+                .to_string();
+            if stream_id == 1 {
+                // tail reports rotation and missing files on stderr
+                out.push(TailLine {
+                    file: String::new(),
+                    stream: String::from("stderr"),
+                    text,
+                    highlights: Vec::new(),
+                });
+                continue;
+            }
+            if self.multiple {
+                if let Some(header) = TAIL_HEADER.captures(&text) {
+                    self.current_file = header[1].to_string();
+                    self.blank_held = false;
+                    continue;
+                }
+                if self.blank_held {
+                    self.blank_held = false;
+                    self.emit(String::new(), matcher, out);
+                }
+                if text.is_empty() {
+                    self.blank_held = true;
+                    continue;
+                }
+            }
+            self.emit(text, matcher, out);
+        }
+    }
+
+    fn emit(&self, text: String, matcher: &LineMatcher, out: &mut Vec<TailLine>) {
+        if !matcher.accept(&text) {
+            return;
+        }
+        out.push(TailLine {
+            file: self.current_file.clone(),
+            stream: String::from("stdout"),
+            highlights: matcher.highlights(&text),
+            text,
+        });
+    }
+}
+
+fn run_tail(
+    app: AppHandle,
+    tail_id: String,
+    session: Session,
+    mut channel: SessionHandle<Channel>,
+    files: Vec<String>,
+    matcher: LineMatcher,
+    stop: Arc<AtomicBool>,
+) {
+    let mut reader = LineReader {
+        multiple: files.len() > 1,
+        current_file: files.first().cloned().unwrap_or_default(),
+        pending: [Vec::new(), Vec::new()],
+        blank_held: false,
+    };
+    let end = pump_exec(
+        &session,
+        &mut channel,
+        true,
+        &[],
+        None,
+        &stop,
+        |stream_id, data| {
+            let mut lines = Vec::new();
+            reader.push(stream_id, data, &matcher, &mut lines);
+            if !lines.is_empty() {
+                let _ = app.emit(
+                    TAIL_LINES_EVENT,
+                    TailLines {
+                        tail_id: tail_id.clone(),
+                        lines,
+                    },
+                );
+            }
+        },
+    );
+    finish_channel(&session, &mut channel);
+    let message = match end {
+        Ok(ExecEnd::Cancelled) => String::from("stopped"),
+        Ok(_) => format!(
+            "tail exited with status {}",
+            channel.exit_status().unwrap_or(-1)
+        ),
+        Err(err) => err,
+    };
+    if let Ok(mut list) = TAIL_MAP.lock() {
+        list.remove(&tail_id);
+    }
+    let _ = app.emit(TAIL_EXIT_EVENT, TailExit { tail_id, message });
+}
+
+// follows the files with `tail -F`, so rotated or recreated logs are picked
+// up again; lines arrive as "ssh_tail_lines" events
+#[tauri::command]
+pub async fn start_ssh_tail(
+    app: AppHandle,
+    session_key: String,
+    files: Vec<String>,
+    options: Option<TailOptions>,
+) -> Result<String, String> {
+    if files.is_empty() {
+        return Err(String::from("no files to follow"));
+    }
+    let options = options.unwrap_or_default();
+    let matcher = LineMatcher::new(&options)?;
+    let session = get_session(&session_key)?;
+    let names: Vec<String> = files.iter().map(|file| shell_quote(file)).collect();
+    let command = format!(
+        "tail -n {} -F -- {}",
+        options.lines.unwrap_or(DEFAULT_BACKFILL),
+        names.join(" ")
+    );
+    let channel = open_killable_channel(&session, &command, None)?;
+
+    let tail_id = Uuid::new_v4().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    TAIL_MAP.lock().map_err(|e| e.to_string())?.insert(
+        tail_id.clone(),
+        Tail {
+            info: TailInfo {
+                tail_id: tail_id.clone(),
+                session_key,
+                files: files.clone(),
+                started_at: timestamp(),
+            },
+            stop: stop.clone(),
+        },
+    );
+    let id = tail_id.clone();
+    thread::spawn(move || run_tail(app, id, session, channel, files, matcher, stop));
+    Ok(tail_id)
+}
+
+// pump_exec kills the remote tail once it sees the flag
+#[tauri::command]
+pub async fn stop_ssh_tail(tail_id: String) -> Result<(), String> {
+    let list = TAIL_MAP.lock().map_err(|e| e.to_string())?;
+    let tail = list.get(&tail_id).ok_or("no tail")?;
+    tail.stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_ssh_tails() -> Result<Vec<TailInfo>, String> {
+    let list = TAIL_MAP.lock().map_err(|e| e.to_string())?;
+    Ok(list.values().map(|tail| tail.info.clone()).collect())
+}
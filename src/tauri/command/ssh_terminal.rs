@@ -0,0 +1,221 @@
+use super::ssh::{
+    attempt, drain_utf8, get_session, is_retryable, read_nonblocking, retry, SessionHandle,
+};
+use super::ssh_exec::finish_channel;
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, Session};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const TERMINAL_OUTPUT_EVENT: &str = "ssh_terminal_output";
+const TERMINAL_EXIT_EVENT: &str = "ssh_terminal_exit";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const READ_BUFFER_SIZE: usize = 8192;
+
+enum TerminalInput {
+    Data(Vec<u8>),
+    Resize(u32, u32),
+    Close,
+}
+
+struct Terminal {
+    session_key: String,
+    sender: Sender<TerminalInput>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TerminalInfo {
+    terminal_id: String,
+    session_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TerminalOutput {
+    terminal_id: String,
+    stream: String,
+    data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TerminalExit {
+    terminal_id: String,
+    exit_status: Option<i32>,
+    message: String,
+}
+
+lazy_static! {
+    static ref TERMINAL_MAP: Arc<Mutex<HashMap<String, Terminal>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+fn send_terminal_input(terminal_id: &str, input: TerminalInput) -> Result<(), String> {
+    let list = TERMINAL_MAP.lock().map_err(|e| e.to_string())?;
+    let terminal = list.get(terminal_id).ok_or("no terminal")?;
+    terminal
+        .sender
+        .send(input)
+        .map_err(|_| String::from("terminal closed"))
+}
+
+fn emit_output(app: &AppHandle, terminal_id: &str, stream: &str, pending: &mut Vec<u8>) {
+    let data = drain_utf8(pending);
+    if data.is_empty() {
+        return;
+    }
+    let _ = app.emit(
+        TERMINAL_OUTPUT_EVENT,
+        TerminalOutput {
+            terminal_id: terminal_id.to_string(),
+            stream: stream.to_string(),
+            data,
+        },
+    );
+}
+
+fn run_terminal(
+    app: AppHandle,
+    terminal_id: String,
+    session: Session,
+    mut channel: SessionHandle<Channel>,
+    receiver: Receiver<TerminalInput>,
+) {
+    let mut buffer = [0u8; READ_BUFFER_SIZE];
+    let mut pending: [Vec<u8>; 2] = [Vec::new(), Vec::new()];
+    // typed or pasted input the channel has not taken yet
+    let mut input: Vec<u8> = Vec::new();
+    let mut message = String::new();
+    'poll: loop {
+        let mut busy = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(TerminalInput::Data(data)) => input.extend_from_slice(&data),
+                Ok(TerminalInput::Resize(cols, rows)) => {
+                    // a failed resize only leaves the old size in place
+                    let _ = retry(&session, || {
+                        channel.request_pty_size(cols, rows, None, None)
+                    });
+                }
+                Ok(TerminalInput::Close) | Err(TryRecvError::Disconnected) => break 'poll,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if !input.is_empty() {
+            // a full window would keep the call lock while the remote waits
+            // for its output to be read, so input is written like it is read
+            match attempt(&session, || channel.write(&input)) {
+                Ok(size) => {
+                    input.drain(..size);
+                    busy = busy || size > 0;
+                }
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => {
+                    message = format!("write error:{}", err);
+                    break 'poll;
+                }
+            }
+        }
+        for (stream_id, stream) in ["stdout", "stderr"].iter().enumerate() {
+            let result =
+                read_nonblocking(&session, &mut channel.stream(stream_id as i32), &mut buffer);
+            match result {
+                Ok(0) => {}
+                Ok(size) => {
+                    pending[stream_id].extend_from_slice(&buffer[..size]);
+                    emit_output(&app, &terminal_id, stream, &mut pending[stream_id]);
+                    busy = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => {
+                    message = format!("read error:{}", err);
+                    break 'poll;
+                }
+            }
+        }
+        if channel.eof() {
+            break;
+        }
+        if !busy {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    finish_channel(&session, &mut channel);
+    let exit_status = channel.exit_status().ok();
+    if let Ok(mut list) = TERMINAL_MAP.lock() {
+        list.remove(&terminal_id);
+    }
+    let _ = app.emit(
+        TERMINAL_EXIT_EVENT,
+        TerminalExit {
+            terminal_id,
+            exit_status,
+            message,
+        },
+    );
+}
+
+#[tauri::command]
+pub async fn open_ssh_terminal(
+    app: AppHandle,
+    session_key: String,
+    cols: u32,
+    rows: u32,
+    term: Option<String>,
+) -> Result<String, String> {
+    let session = get_session(&session_key)?;
+    let mut channel = retry(&session, || session.channel_session())
+        .map(|channel| SessionHandle::new(&session, channel))
+        .map_err(|e| format!("get channel error:{}", e))?;
+    let term = term.as_deref().unwrap_or("xterm-256color");
+    retry(&session, || {
+        channel.request_pty(term, None, Some((cols, rows, 0, 0)))
+    })
+    .map_err(|e| format!("request pty error:{}", e))?;
+    retry(&session, || channel.shell()).map_err(|e| format!("start shell error:{}", e))?;
+
+    let terminal_id = Uuid::new_v4().to_string();
+    let (sender, receiver) = mpsc::channel();
+    TERMINAL_MAP.lock().map_err(|e| e.to_string())?.insert(
+        terminal_id.clone(),
+        Terminal {
+            session_key,
+            sender,
+        },
+    );
+    let id = terminal_id.clone();
+    thread::spawn(move || run_terminal(app, id, session, channel, receiver));
+    Ok(terminal_id)
+}
+
+#[tauri::command]
+pub async fn write_ssh_terminal(terminal_id: String, data: String) -> Result<(), String> {
+    send_terminal_input(&terminal_id, TerminalInput::Data(data.into_bytes()))
+}
+
+#[tauri::command]
+pub async fn resize_ssh_terminal(terminal_id: String, cols: u32, rows: u32) -> Result<(), String> {
+    send_terminal_input(&terminal_id, TerminalInput::Resize(cols, rows))
+}
+
+#[tauri::command]
+pub async fn close_ssh_terminal(terminal_id: String) -> Result<(), String> {
+    send_terminal_input(&terminal_id, TerminalInput::Close)
+}
+
+#[tauri::command]
+pub async fn list_ssh_terminals() -> Result<Vec<TerminalInfo>, String> {
+    let list = TERMINAL_MAP.lock().map_err(|e| e.to_string())?;
+    Ok(list
+        .iter()
+        .map(|(terminal_id, terminal)| TerminalInfo {
+            terminal_id: terminal_id.clone(),
+            session_key: terminal.session_key.clone(),
+        })
+        .collect())
+}
@@ -0,0 +1,811 @@
+use super::ssh::{
+    get_session, is_retryable, retry, write_all_retrying, SessionHandle, SessionStream, SftpClient,
+};
+use super::ssh_sync::run_sync;
+use super::tree::{
+    join_remote, local_mode, remote_sums, set_local_mode, set_local_times, sftp_create_dir_all,
+    times_stat, walk_local, walk_remote, TreeEntry,
+};
+use crate::toolbox::file::{file_hash, PathFilter};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use ssh2::{FileStat, OpenFlags, OpenType};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+const MAX_CONCURRENT_TRANSFERS: usize = 3;
+const WAIT_INTERVAL: Duration = Duration::from_millis(200);
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_TRANSFERRING: &str = "transferring";
+const STATUS_PAUSED: &str = "paused";
+const STATUS_VERIFYING: &str = "verifying";
+const STATUS_SUCCESS: &str = "success";
+const STATUS_FAILURE: &str = "failure";
+const STATUS_CANCELLED: &str = "cancelled";
+
+pub const DIRECTION_UPLOAD: &str = "upload";
+pub const DIRECTION_DOWNLOAD: &str = "download";
+
+const KIND_FILE: &str = "file";
+const KIND_DIRECTORY: &str = "directory";
+pub const KIND_SYNC: &str = "sync";
+
+const VERIFY_ALGORITHMS: [&str; 2] = ["sha256", "md5"];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TransferInfo {
+    pub id: String,
+    pub session_key: String,
+    pub direction: String,
+    pub kind: String,
+    pub local_file: String,
+    pub remote_file: String,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub resume: bool,
+    pub verify: String,
+    pub checksum: String,
+    // sync only: size_mtime or hash, and whether extraneous target files go
+    pub compare: String,
+    pub delete: bool,
+    pub total: u64,
+    pub current: u64,
+    pub files_total: u64,
+    pub files_done: u64,
+    pub current_file: String,
+    pub status: String,
+    pub message: String,
+    pub created_at: u64,
+    pub finished_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TransferOptions {
+    // continue from an existing partial destination file
+    resume: Option<bool>,
+    // sha256 or md5, checked against the remote host once the copy is done
+    verify: Option<String>,
+}
+
+struct Transfer {
+    info: TransferInfo,
+    cancel: Arc<AtomicBool>,
+    pause: Arc<AtomicBool>,
+}
+
+lazy_static! {
+    static ref TRANSFER_MAP: Arc<Mutex<HashMap<String, Transfer>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    static ref TRANSFER_SLOTS: Arc<Semaphore> = Arc::new(Semaphore::new(MAX_CONCURRENT_TRANSFERS));
+}
+
+fn update_transfer<F: FnOnce(&mut TransferInfo)>(id: &str, f: F) {
+    if let Ok(mut list) = TRANSFER_MAP.lock() {
+        if let Some(transfer) = list.get_mut(id) {
+            f(&mut transfer.info);
+        }
+    }
+}
+
+pub struct TransferContext {
+    id: String,
+    cancel: Arc<AtomicBool>,
+    pause: Arc<AtomicBool>,
+}
+
+impl TransferContext {
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    // blocks while the transfer is paused, fails once it is cancelled
+    pub fn checkpoint(&self) -> Result<(), String> {
+        while self.pause.load(Ordering::SeqCst) && !self.cancelled() {
+            thread::sleep(WAIT_INTERVAL);
+        }
+        if self.cancelled() {
+            return Err(String::from(STATUS_CANCELLED));
+        }
+        Ok(())
+    }
+
+    fn set_total(&self, total: u64) {
+        update_transfer(&self.id, |info| info.total = total);
+    }
+
+    pub fn begin(&self, total: u64, files: u64) {
+        update_transfer(&self.id, |info| {
+            info.total = total;
+            info.files_total = files;
+            info.files_done = 0;
+        });
+    }
+
+    fn advance(&self, size: u64) {
+        update_transfer(&self.id, |info| info.current += size);
+    }
+
+    fn start_file(&self, relative: &str) {
+        update_transfer(&self.id, |info| info.current_file = relative.to_string());
+    }
+
+    fn finish_file(&self) {
+        update_transfer(&self.id, |info| info.files_done += 1);
+    }
+
+    // leaves a remark on the record that stays once the transfer succeeds
+    pub fn note(&self, message: String) {
+        update_transfer(&self.id, |info| info.message = message);
+    }
+}
+
+fn write_retrying(writer: &mut impl Write, data: &[u8]) -> Result<(), String> {
+    write_all_retrying(writer, data).map_err(|e| format!("write error:{}", e))
+}
+
+fn copy_with_progress(
+    ctx: &TransferContext,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> Result<u64, String> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        ctx.checkpoint()?;
+        let size = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(err) if is_retryable(&err) => {
+                thread::sleep(RETRY_INTERVAL);
+                continue;
+            }
+            Err(err) => return Err(format!("read error:{}", err)),
+        };
+        write_retrying(writer, &buffer[..size])?;
+        ctx.advance(size as u64);
+        copied += size as u64;
+    }
+    Ok(copied)
+}
+
+fn upload_file(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let session = get_session(&info.session_key)?;
+    let metadata = fs::metadata(&info.local_file).map_err(|e| e.to_string())?;
+    ctx.set_total(metadata.len());
+    let mut local = fs::File::open(&info.local_file).map_err(|e| e.to_string())?;
+    let mut remote_channel = retry(&session, || {
+        session.scp_send(Path::new(&info.remote_file), 0o644, metadata.len(), None)
+    })
+    .map(|channel| SessionHandle::new(&session, channel))
+    .map_err(|e| format!("scp send error:{}", e))?;
+    copy_with_progress(
+        ctx,
+        &mut local,
+        &mut SessionStream::new(&session, &mut remote_channel),
+    )?;
+    let _ = retry(&session, || remote_channel.send_eof());
+    let _ = retry(&session, || remote_channel.wait_eof());
+    let _ = retry(&session, || remote_channel.close());
+    let _ = retry(&session, || remote_channel.wait_close());
+    Ok(())
+}
+
+fn download_file(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let session = get_session(&info.session_key)?;
+    let (remote_channel, stat) = retry(&session, || session.scp_recv(Path::new(&info.remote_file)))
+        .map_err(|e| format!("scp recv error:{}", e))?;
+    let mut remote_channel = SessionHandle::new(&session, remote_channel);
+    ctx.set_total(stat.size());
+    let file_path = Path::new(&info.local_file);
+    let save_dir = file_path.parent().ok_or("no parent dir")?;
+    fs::create_dir_all(save_dir).map_err(|e| format!("create dir error:{}", e))?;
+    let mut local = fs::File::create(file_path).map_err(|e| format!("create file error:{}", e))?;
+    let result = copy_with_progress(
+        ctx,
+        &mut SessionStream::new(&session, &mut remote_channel),
+        &mut local,
+    );
+    let _ = retry(&session, || remote_channel.send_eof());
+    let _ = retry(&session, || remote_channel.wait_eof());
+    let _ = retry(&session, || remote_channel.close());
+    let _ = retry(&session, || remote_channel.wait_close());
+    result.map(|_| ())
+}
+
+// the remote file is reused as long as it is not larger than the local one;
+// anything else starts over
+fn upload_file_resumable(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let sftp = SftpClient::open(&get_session(&info.session_key)?)?;
+    let local_size = fs::metadata(&info.local_file)
+        .map_err(|e| e.to_string())?
+        .len();
+    ctx.set_total(local_size);
+    let remote_path = Path::new(&info.remote_file);
+    let offset = match sftp.call(|s| s.stat(remote_path)) {
+        Ok(stat) if stat.is_file() && stat.size.unwrap_or(0) <= local_size => {
+            stat.size.unwrap_or(0)
+        }
+        _ => 0,
+    };
+    let mut flags = OpenFlags::WRITE | OpenFlags::CREATE;
+    if offset == 0 {
+        flags |= OpenFlags::TRUNCATE;
+    }
+    let mut remote = sftp
+        .open_file(|s| s.open_mode(remote_path, flags, 0o644, OpenType::File))
+        .map_err(|e| format!("open remote {} error:{}", info.remote_file, e))?;
+    remote
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("seek remote error:{}", e))?;
+    let mut local = fs::File::open(&info.local_file).map_err(|e| e.to_string())?;
+    local
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("seek local error:{}", e))?;
+    ctx.advance(offset);
+    copy_with_progress(ctx, &mut local, &mut sftp.stream(&mut remote))?;
+    Ok(())
+}
+
+fn download_file_resumable(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let sftp = SftpClient::open(&get_session(&info.session_key)?)?;
+    let mut remote = sftp
+        .open_file(|s| s.open(Path::new(&info.remote_file)))
+        .map_err(|e| format!("open remote {} error:{}", info.remote_file, e))?;
+    let remote_size = retry(sftp.session(), || remote.stat())
+        .map_err(|e| format!("stat remote error:{}", e))?
+        .size
+        .unwrap_or(0);
+    ctx.set_total(remote_size);
+    let file_path = Path::new(&info.local_file);
+    let save_dir = file_path.parent().ok_or("no parent dir")?;
+    fs::create_dir_all(save_dir).map_err(|e| format!("create dir error:{}", e))?;
+    let offset = match fs::metadata(file_path) {
+        Ok(metadata) if metadata.is_file() && metadata.len() <= remote_size => metadata.len(),
+        _ => 0,
+    };
+    let mut local = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(offset == 0)
+        .open(file_path)
+        .map_err(|e| format!("create file error:{}", e))?;
+    local
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("seek local error:{}", e))?;
+    remote
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("seek remote error:{}", e))?;
+    ctx.advance(offset);
+    copy_with_progress(ctx, &mut sftp.stream(&mut remote), &mut local)?;
+    Ok(())
+}
+
+fn remote_hash(info: &TransferInfo) -> Result<String, String> {
+    let session = get_session(&info.session_key)?;
+    let (dir, name) = match info.remote_file.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((dir, name)) => (dir, name),
+        None => (".", info.remote_file.as_str()),
+    };
+    remote_sums(&session, &info.verify, dir, &[name])?
+        .remove(name)
+        .ok_or_else(|| format!("{}sum error:no output for {}", info.verify, name))
+}
+
+// a corrupt copy is removed so that a resumed retry does not keep it
+fn verify_transfer(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    ctx.checkpoint()?;
+    update_transfer(&ctx.id, |info| info.status = String::from(STATUS_VERIFYING));
+    let local = file_hash(Path::new(&info.local_file), &info.verify)?;
+    let remote = remote_hash(info)?;
+    if local != remote {
+        if info.direction == DIRECTION_UPLOAD {
+            if let Ok(sftp) =
+                get_session(&info.session_key).and_then(|session| SftpClient::open(&session))
+            {
+                let _ = sftp.call(|s| s.unlink(Path::new(&info.remote_file)));
+            }
+        } else {
+            let _ = fs::remove_file(&info.local_file);
+        }
+        return Err(format!(
+            "{} mismatch, local {} remote {}",
+            info.verify, local, remote
+        ));
+    }
+    update_transfer(&ctx.id, |info| info.checksum = local);
+    Ok(())
+}
+
+pub fn transfer_filter(info: &TransferInfo) -> Result<PathFilter, String> {
+    PathFilter::new(&info.include, &info.exclude)
+}
+
+fn begin_tree(ctx: &TransferContext, entries: &[TreeEntry]) {
+    let total = entries.iter().filter(|e| !e.is_dir).map(|e| e.size).sum();
+    let files = entries.iter().filter(|e| !e.is_dir).count() as u64;
+    ctx.begin(total, files);
+}
+
+// copies one file of a tree and carries its mode and times over
+pub fn upload_entry(
+    ctx: &TransferContext,
+    sftp: &SftpClient,
+    local_root: &Path,
+    remote_root: &str,
+    entry: &TreeEntry,
+) -> Result<(), String> {
+    ctx.start_file(&entry.relative);
+    let remote = join_remote(remote_root, &entry.relative);
+    let mut local = fs::File::open(local_root.join(&entry.relative))
+        .map_err(|e| format!("open {} error:{}", entry.relative, e))?;
+    let mut remote_file = sftp
+        .open_file(|s| {
+            s.open_mode(
+                Path::new(&remote),
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+                entry.mode as i32,
+                OpenType::File,
+            )
+        })
+        .map_err(|e| format!("open remote {} error:{}", remote, e))?;
+    copy_with_progress(ctx, &mut local, &mut sftp.stream(&mut remote_file))?;
+    drop(remote_file);
+    sftp.call(|s| s.setstat(Path::new(&remote), times_stat(entry)))
+        .map_err(|e| format!("setstat {} error:{}", remote, e))?;
+    ctx.finish_file();
+    Ok(())
+}
+
+pub fn download_entry(
+    ctx: &TransferContext,
+    sftp: &SftpClient,
+    local_root: &Path,
+    remote_root: &str,
+    entry: &TreeEntry,
+) -> Result<(), String> {
+    ctx.start_file(&entry.relative);
+    let local_path = local_root.join(&entry.relative);
+    let remote = join_remote(remote_root, &entry.relative);
+    let mut remote_file = sftp
+        .open_file(|s| s.open(Path::new(&remote)))
+        .map_err(|e| format!("open remote {} error:{}", remote, e))?;
+    let mut local = fs::File::create(&local_path)
+        .map_err(|e| format!("create file {} error:{}", entry.relative, e))?;
+    copy_with_progress(ctx, &mut sftp.stream(&mut remote_file), &mut local)?;
+    drop(local);
+    let _ = set_local_times(&local_path, entry.atime, entry.mtime);
+    let _ = set_local_mode(&local_path, entry.mode);
+    ctx.finish_file();
+    Ok(())
+}
+
+fn upload_dir(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let sftp = SftpClient::open(&get_session(&info.session_key)?)?;
+    let root = Path::new(&info.local_file);
+    let mut entries = Vec::new();
+    walk_local(root, "", &transfer_filter(info)?, &mut entries)?;
+    begin_tree(ctx, &entries);
+
+    // directories stay writable until their children are in place, a read
+    // only source directory gets its mode in the last pass
+    let root_created = sftp.call(|s| s.stat(Path::new(&info.remote_file))).is_err();
+    sftp_create_dir_all(&sftp, &info.remote_file, 0o755)?;
+    for entry in entries.iter() {
+        ctx.checkpoint()?;
+        let remote = join_remote(&info.remote_file, &entry.relative);
+        if entry.is_dir {
+            sftp_create_dir_all(&sftp, &remote, 0o755)?;
+            continue;
+        }
+        upload_entry(ctx, &sftp, root, &info.remote_file, entry)?;
+    }
+    // directory mtimes change while their children are written, so they are
+    // applied last, deepest first
+    for entry in entries.iter().rev().filter(|e| e.is_dir) {
+        let remote = join_remote(&info.remote_file, &entry.relative);
+        let _ = sftp.call(|s| s.setstat(Path::new(&remote), times_stat(entry)));
+    }
+    if root_created {
+        let root_mode = fs::metadata(root).map(|m| local_mode(&m)).unwrap_or(0o755);
+        let _ = sftp.call(|s| {
+            s.setstat(
+                Path::new(&info.remote_file),
+                FileStat {
+                    size: None,
+                    uid: None,
+                    gid: None,
+                    perm: Some(root_mode),
+                    atime: None,
+                    mtime: None,
+                },
+            )
+        });
+    }
+    Ok(())
+}
+
+fn download_dir(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    let sftp = SftpClient::open(&get_session(&info.session_key)?)?;
+    let mut entries = Vec::new();
+    walk_remote(
+        &sftp,
+        &info.remote_file,
+        "",
+        &transfer_filter(info)?,
+        &mut entries,
+    )?;
+    begin_tree(ctx, &entries);
+
+    let root = PathBuf::from(&info.local_file);
+    fs::create_dir_all(&root).map_err(|e| format!("create dir error:{}", e))?;
+    for entry in entries.iter() {
+        ctx.checkpoint()?;
+        let local_path = root.join(&entry.relative);
+        if entry.is_dir {
+            fs::create_dir_all(&local_path).map_err(|e| format!("create dir error:{}", e))?;
+            continue;
+        }
+        download_entry(ctx, &sftp, &root, &info.remote_file, entry)?;
+    }
+    for entry in entries.iter().rev().filter(|e| e.is_dir) {
+        let local_path = root.join(&entry.relative);
+        let _ = set_local_mode(&local_path, entry.mode);
+        let _ = set_local_times(&local_path, entry.atime, entry.mtime);
+    }
+    Ok(())
+}
+
+fn run_transfer(ctx: &TransferContext, info: &TransferInfo) -> Result<(), String> {
+    ctx.checkpoint()?;
+    update_transfer(&ctx.id, |info| {
+        info.status = String::from(STATUS_TRANSFERRING)
+    });
+    match (info.direction.as_str(), info.kind.as_str(), info.resume) {
+        (_, KIND_SYNC, _) => run_sync(ctx, info),
+        (DIRECTION_UPLOAD, KIND_DIRECTORY, _) => upload_dir(ctx, info),
+        (DIRECTION_UPLOAD, _, true) => upload_file_resumable(ctx, info),
+        (DIRECTION_UPLOAD, _, false) => upload_file(ctx, info),
+        (_, KIND_DIRECTORY, _) => download_dir(ctx, info),
+        (_, _, true) => download_file_resumable(ctx, info),
+        _ => download_file(ctx, info),
+    }?;
+    if info.kind == KIND_FILE && !info.verify.is_empty() {
+        verify_transfer(ctx, info)?;
+    }
+    Ok(())
+}
+
+fn finish_transfer(ctx: &TransferContext, result: Result<(), String>) {
+    let cancelled = ctx.cancelled();
+    update_transfer(&ctx.id, |info| {
+        info.finished_at = timestamp();
+        match result {
+            Ok(_) => {
+                info.status = String::from(STATUS_SUCCESS);
+                info.current = info.total;
+            }
+            Err(_) if cancelled => info.status = String::from(STATUS_CANCELLED),
+            Err(err) => {
+                info.status = String::from(STATUS_FAILURE);
+                info.message = err;
+            }
+        }
+    });
+}
+
+// registers a fresh record under `id` (reusing it on retry) and returns the
+// context its worker runs with
+fn prepare_transfer(mut info: TransferInfo) -> Result<(TransferContext, TransferInfo), String> {
+    info.total = 0;
+    info.current = 0;
+    info.files_total = 0;
+    info.files_done = 0;
+    info.current_file = String::new();
+    info.checksum = String::new();
+    info.status = String::from(STATUS_PENDING);
+    info.message = String::new();
+    info.finished_at = 0;
+    let ctx = TransferContext {
+        id: info.id.clone(),
+        cancel: Arc::new(AtomicBool::new(false)),
+        pause: Arc::new(AtomicBool::new(false)),
+    };
+    TRANSFER_MAP.lock().map_err(|e| e.to_string())?.insert(
+        info.id.clone(),
+        Transfer {
+            info: info.clone(),
+            cancel: ctx.cancel.clone(),
+            pause: ctx.pause.clone(),
+        },
+    );
+    Ok((ctx, info))
+}
+
+pub fn start_transfer(info: TransferInfo) -> Result<String, String> {
+    let (ctx, info) = prepare_transfer(info)?;
+    let id = info.id.clone();
+    let worker_id = id.clone();
+    tokio::spawn(async move {
+        let _permit = TRANSFER_SLOTS.clone().acquire_owned().await;
+        let result = tokio::task::spawn_blocking(move || {
+            let result = run_transfer(&ctx, &info);
+            finish_transfer(&ctx, result);
+        })
+        .await;
+        // a panicking worker never got to finish_transfer
+        if let Err(err) = result {
+            update_transfer(&worker_id, |info| {
+                info.finished_at = timestamp();
+                info.status = String::from(STATUS_FAILURE);
+                info.message = format!("transfer worker error:{}", err);
+            });
+        }
+    });
+    Ok(id)
+}
+
+pub fn new_transfer_info(
+    session_key: String,
+    direction: &str,
+    local_file: String,
+    remote_file: String,
+) -> TransferInfo {
+    TransferInfo {
+        id: Uuid::new_v4().to_string(),
+        session_key,
+        direction: String::from(direction),
+        kind: String::from(KIND_FILE),
+        local_file,
+        remote_file,
+        include: Vec::new(),
+        exclude: Vec::new(),
+        resume: false,
+        verify: String::new(),
+        checksum: String::new(),
+        compare: String::new(),
+        delete: false,
+        total: 0,
+        current: 0,
+        files_total: 1,
+        files_done: 0,
+        current_file: String::new(),
+        status: String::from(STATUS_PENDING),
+        message: String::new(),
+        created_at: timestamp(),
+        finished_at: 0,
+    }
+}
+
+fn apply_options(info: &mut TransferInfo, options: Option<TransferOptions>) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    let verify = options.verify.unwrap_or_default().to_lowercase();
+    if !verify.is_empty() && !VERIFY_ALGORITHMS.contains(&verify.as_str()) {
+        return Err(format!("unsupported verify algorithm:{}", verify));
+    }
+    info.resume = options.resume.unwrap_or(false);
+    info.verify = verify;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn download_remote_file(
+    session_key: String,
+    local_file: String,
+    remote_file: String,
+    options: Option<TransferOptions>,
+) -> Result<String, String> {
+    get_session(&session_key)?;
+    let mut info = new_transfer_info(session_key, DIRECTION_DOWNLOAD, local_file, remote_file);
+    apply_options(&mut info, options)?;
+    start_transfer(info)
+}
+
+#[tauri::command]
+pub async fn upload_remote_file(
+    session_key: String,
+    local_file: String,
+    remote_file: String,
+    options: Option<TransferOptions>,
+) -> Result<String, String> {
+    get_session(&session_key)?;
+    let mut info = new_transfer_info(session_key, DIRECTION_UPLOAD, local_file, remote_file);
+    apply_options(&mut info, options)?;
+    start_transfer(info)
+}
+
+pub fn new_dir_transfer_info(
+    session_key: String,
+    direction: &str,
+    local_dir: String,
+    remote_dir: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<TransferInfo, String> {
+    let mut info = new_transfer_info(session_key, direction, local_dir, remote_dir);
+    info.kind = String::from(KIND_DIRECTORY);
+    info.include = include.unwrap_or_default();
+    info.exclude = exclude.unwrap_or_default();
+    // reject bad patterns up front rather than in the worker
+    transfer_filter(&info)?;
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn upload_remote_dir(
+    session_key: String,
+    local_dir: String,
+    remote_dir: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<String, String> {
+    get_session(&session_key)?;
+    if !Path::new(&local_dir).is_dir() {
+        return Err(format!("{} is not a directory", local_dir));
+    }
+    start_transfer(new_dir_transfer_info(
+        session_key,
+        DIRECTION_UPLOAD,
+        local_dir,
+        remote_dir,
+        include,
+        exclude,
+    )?)
+}
+
+#[tauri::command]
+pub async fn download_remote_dir(
+    session_key: String,
+    local_dir: String,
+    remote_dir: String,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<String, String> {
+    get_session(&session_key)?;
+    start_transfer(new_dir_transfer_info(
+        session_key,
+        DIRECTION_DOWNLOAD,
+        local_dir,
+        remote_dir,
+        include,
+        exclude,
+    )?)
+}
+
+#[tauri::command]
+pub async fn upload_remote_file_sync(
+    session_key: String,
+    local_file: String,
+    remote_file: String,
+) -> Result<String, String> {
+    let info = new_transfer_info(session_key, DIRECTION_UPLOAD, local_file, remote_file);
+    let (ctx, info) = prepare_transfer(info)?;
+    let _permit = TRANSFER_SLOTS
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = tokio::task::spawn_blocking(move || {
+        let result = run_transfer(&ctx, &info);
+        finish_transfer(&ctx, result.clone());
+        result
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    result.map(|_| String::from("success"))
+}
+
+// the single transfer commands from before the queue: progress of the most
+// recent transfer, and a cancel for whatever is running
+#[tauri::command]
+pub async fn get_transfer_remote_progress() -> Result<TransferInfo, String> {
+    let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    list.values()
+        .max_by_key(|t| t.info.created_at)
+        .map(|t| t.info.clone())
+        .ok_or_else(|| String::from("no transfer"))
+}
+
+#[tauri::command]
+pub async fn send_cancel_signal() -> Result<bool, String> {
+    let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    for transfer in list.values() {
+        let status = transfer.info.status.as_str();
+        if [
+            STATUS_PENDING,
+            STATUS_TRANSFERRING,
+            STATUS_PAUSED,
+            STATUS_VERIFYING,
+        ]
+        .contains(&status)
+        {
+            transfer.cancel.store(true, Ordering::SeqCst);
+        }
+    }
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn list_transfers() -> Result<Vec<TransferInfo>, String> {
+    let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let mut transfers: Vec<TransferInfo> = list.values().map(|t| t.info.clone()).collect();
+    transfers.sort_by_key(|t| t.created_at);
+    Ok(transfers)
+}
+
+#[tauri::command]
+pub async fn get_transfer(id: String) -> Result<TransferInfo, String> {
+    let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let transfer = list.get(&id).ok_or("no transfer")?;
+    Ok(transfer.info.clone())
+}
+
+#[tauri::command]
+pub async fn pause_transfer(id: String) -> Result<(), String> {
+    let mut list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let transfer = list.get_mut(&id).ok_or("no transfer")?;
+    if transfer.info.status != STATUS_TRANSFERRING {
+        return Err(format!("transfer is {}", transfer.info.status));
+    }
+    transfer.pause.store(true, Ordering::SeqCst);
+    transfer.info.status = String::from(STATUS_PAUSED);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn resume_transfer(id: String) -> Result<(), String> {
+    let mut list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let transfer = list.get_mut(&id).ok_or("no transfer")?;
+    if transfer.info.status != STATUS_PAUSED {
+        return Err(format!("transfer is {}", transfer.info.status));
+    }
+    transfer.pause.store(false, Ordering::SeqCst);
+    transfer.info.status = String::from(STATUS_TRANSFERRING);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn cancel_transfer(id: String) -> Result<(), String> {
+    let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let transfer = list.get(&id).ok_or("no transfer")?;
+    transfer.cancel.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn retry_transfer(id: String) -> Result<String, String> {
+    let info = {
+        let list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+        let transfer = list.get(&id).ok_or("no transfer")?;
+        if transfer.info.status != STATUS_FAILURE && transfer.info.status != STATUS_CANCELLED {
+            return Err(format!("transfer is {}", transfer.info.status));
+        }
+        transfer.info.clone()
+    };
+    start_transfer(info)
+}
+
+#[tauri::command]
+pub async fn remove_transfer(id: String) -> Result<(), String> {
+    let mut list = TRANSFER_MAP.lock().map_err(|e| e.to_string())?;
+    let transfer = list.get(&id).ok_or("no transfer")?;
+    if transfer.info.status == STATUS_PENDING
+        || transfer.info.status == STATUS_TRANSFERRING
+        || transfer.info.status == STATUS_PAUSED
+    {
+        return Err(format!("transfer is {}", transfer.info.status));
+    }
+    list.remove(&id);
+    Ok(())
+}
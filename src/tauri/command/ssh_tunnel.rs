@@ -0,0 +1,498 @@
+use super::ssh::{
+    attempt, get_session, is_retryable, read_nonblocking, retry, write_all_retrying, SessionHandle,
+};
+use crate::toolbox::time::timestamp;
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, Session};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+const SOCKS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BIND_HOST: &str = "127.0.0.1";
+const TUNNEL_STATUS_EVENT: &str = "ssh_tunnel_status";
+
+const KIND_LOCAL: &str = "local";
+const KIND_REMOTE: &str = "remote";
+const KIND_DYNAMIC: &str = "dynamic";
+
+#[derive(Default)]
+pub struct TunnelState {
+    stop: AtomicBool,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    active_connections: AtomicU64,
+    total_connections: AtomicU64,
+    message: Mutex<String>,
+    tunnel_id: String,
+    app: Option<AppHandle>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TunnelStatus {
+    tunnel_id: String,
+    running: bool,
+    message: String,
+}
+
+impl TunnelState {
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    // a failed connection leaves the tunnel running; the error is kept as
+    // the tunnel message and sent to the frontend
+    fn report(&self, message: String) {
+        if let Ok(mut current) = self.message.lock() {
+            *current = message.clone();
+        }
+        if let Some(app) = &self.app {
+            let _ = app.emit(
+                TUNNEL_STATUS_EVENT,
+                TunnelStatus {
+                    tunnel_id: self.tunnel_id.clone(),
+                    running: !self.stopped(),
+                    message,
+                },
+            );
+        }
+    }
+
+    fn fail(&self, message: String) {
+        self.stop();
+        self.report(message);
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TunnelInfo {
+    id: String,
+    session_key: String,
+    kind: String,
+    bind_host: String,
+    bind_port: u16,
+    target_host: String,
+    target_port: u16,
+    created_at: u64,
+    running: bool,
+    bytes_sent: u64,
+    bytes_received: u64,
+    active_connections: u64,
+    total_connections: u64,
+    message: String,
+}
+
+struct Tunnel {
+    info: TunnelInfo,
+    state: Arc<TunnelState>,
+}
+
+impl Tunnel {
+    fn snapshot(&self) -> TunnelInfo {
+        let mut info = self.info.clone();
+        info.running = !self.state.stopped();
+        info.bytes_sent = self.state.bytes_sent.load(Ordering::SeqCst);
+        info.bytes_received = self.state.bytes_received.load(Ordering::SeqCst);
+        info.active_connections = self.state.active_connections.load(Ordering::SeqCst);
+        info.total_connections = self.state.total_connections.load(Ordering::SeqCst);
+        info.message = self
+            .state
+            .message
+            .lock()
+            .map(|m| m.clone())
+            .unwrap_or_default();
+        info
+    }
+}
+
+lazy_static! {
+    static ref TUNNEL_MAP: Arc<Mutex<HashMap<String, Tunnel>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+// shuttles bytes between a local socket and an ssh channel until either side
+// closes or the tunnel is stopped
+pub fn pump_channel(
+    session: &Session,
+    channel: &mut Channel,
+    socket: &mut TcpStream,
+    state: &TunnelState,
+) -> std::io::Result<()> {
+    socket.set_nonblocking(true)?;
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    // read from the socket but not yet taken by the channel
+    let mut outgoing: Vec<u8> = Vec::new();
+    while !state.stopped() {
+        let mut busy = false;
+        if outgoing.is_empty() {
+            match socket.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    outgoing.extend_from_slice(&buffer[..size]);
+                    busy = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !outgoing.is_empty() {
+            // a full window would keep the call lock while the peer waits for
+            // its output to be read, so the channel is written like it is read
+            match attempt(session, || channel.write(&outgoing)) {
+                Ok(size) => {
+                    outgoing.drain(..size);
+                    state.bytes_sent.fetch_add(size as u64, Ordering::SeqCst);
+                    busy = busy || size > 0;
+                }
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        match read_nonblocking(session, channel, &mut buffer) {
+            Ok(0) => {
+                if channel.eof() {
+                    break;
+                }
+            }
+            Ok(size) => {
+                write_all_retrying(socket, &buffer[..size])?;
+                state
+                    .bytes_received
+                    .fetch_add(size as u64, Ordering::SeqCst);
+                busy = true;
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        if !busy {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let _ = retry(session, || channel.send_eof());
+    let _ = retry(session, || channel.close());
+    Ok(())
+}
+
+fn serve_connection(
+    session: Session,
+    mut channel: SessionHandle<Channel>,
+    mut socket: TcpStream,
+    state: Arc<TunnelState>,
+) {
+    state.active_connections.fetch_add(1, Ordering::SeqCst);
+    state.total_connections.fetch_add(1, Ordering::SeqCst);
+    if let Err(err) = pump_channel(&session, &mut channel, &mut socket, &state) {
+        state.report(format!("tunnel connection error:{}", err));
+    }
+    state.active_connections.fetch_sub(1, Ordering::SeqCst);
+}
+
+fn open_direct_channel(
+    session: &Session,
+    host: &str,
+    port: u16,
+    peer: SocketAddr,
+) -> Result<SessionHandle<Channel>, String> {
+    let source = peer.ip().to_string();
+    retry(session, || {
+        session.channel_direct_tcpip(host, port, Some((source.as_str(), peer.port())))
+    })
+    .map(|channel| SessionHandle::new(session, channel))
+    .map_err(|e| format!("open channel to {}:{} error:{}", host, port, e))
+}
+
+fn accept_local<F>(listener: TcpListener, state: Arc<TunnelState>, handle: F)
+where
+    F: Fn(TcpStream, SocketAddr) + Send + Clone + 'static,
+{
+    while !state.stopped() {
+        match listener.accept() {
+            Ok((socket, peer)) => {
+                let handle = handle.clone();
+                thread::spawn(move || handle(socket, peer));
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(err) => state.fail(format!("accept error:{}", err)),
+        }
+    }
+}
+
+fn bind_local(bind_host: &str, bind_port: u16) -> Result<(TcpListener, u16), String> {
+    let listener = TcpListener::bind((bind_host, bind_port))
+        .map_err(|e| format!("bind {}:{} error:{}", bind_host, bind_port, e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    Ok((listener, port))
+}
+
+fn register_tunnel(
+    info: TunnelInfo,
+    app: AppHandle,
+) -> Result<(TunnelInfo, Arc<TunnelState>), String> {
+    let state = Arc::new(TunnelState {
+        tunnel_id: info.id.clone(),
+        app: Some(app),
+        ..Default::default()
+    });
+    let tunnel = Tunnel {
+        info,
+        state: state.clone(),
+    };
+    let snapshot = tunnel.snapshot();
+    TUNNEL_MAP
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(snapshot.id.clone(), tunnel);
+    Ok((snapshot, state))
+}
+
+fn new_tunnel_info(
+    session_key: String,
+    kind: &str,
+    bind_host: String,
+    bind_port: u16,
+    target_host: String,
+    target_port: u16,
+) -> TunnelInfo {
+    TunnelInfo {
+        id: Uuid::new_v4().to_string(),
+        session_key,
+        kind: String::from(kind),
+        bind_host,
+        bind_port,
+        target_host,
+        target_port,
+        created_at: timestamp(),
+        running: true,
+        bytes_sent: 0,
+        bytes_received: 0,
+        active_connections: 0,
+        total_connections: 0,
+        message: String::new(),
+    }
+}
+
+// minimal SOCKS5 server side: no authentication, CONNECT only
+fn socks5_handshake(socket: &mut TcpStream) -> Result<(String, u16), String> {
+    let mut header = [0u8; 2];
+    socket.read_exact(&mut header).map_err(|e| e.to_string())?;
+    if header[0] != 5 {
+        return Err(format!("unsupported socks version {}", header[0]));
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    socket.read_exact(&mut methods).map_err(|e| e.to_string())?;
+    if !methods.contains(&0) {
+        let _ = socket.write_all(&[5, 0xff]);
+        return Err(String::from("socks client requires authentication"));
+    }
+    socket.write_all(&[5, 0]).map_err(|e| e.to_string())?;
+
+    let mut request = [0u8; 4];
+    socket.read_exact(&mut request).map_err(|e| e.to_string())?;
+    if request[1] != 1 {
+        let _ = socks5_reply(socket, 7);
+        return Err(format!("unsupported socks command {}", request[1]));
+    }
+    let host = match request[3] {
+        1 => {
+            let mut addr = [0u8; 4];
+            socket.read_exact(&mut addr).map_err(|e| e.to_string())?;
+            Ipv4Addr::from(addr).to_string()
+        }
+        3 => {
+            let mut len = [0u8; 1];
+            socket.read_exact(&mut len).map_err(|e| e.to_string())?;
+            let mut name = vec![0u8; len[0] as usize];
+            socket.read_exact(&mut name).map_err(|e| e.to_string())?;
+            String::from_utf8_lossy(&name).to_string()
+        }
+        4 => {
+            let mut addr = [0u8; 16];
+            socket.read_exact(&mut addr).map_err(|e| e.to_string())?;
+            Ipv6Addr::from(addr).to_string()
+        }
+        kind => {
+            let _ = socks5_reply(socket, 8);
+            return Err(format!("unsupported socks address type {}", kind));
+        }
+    };
+    let mut port = [0u8; 2];
+    socket.read_exact(&mut port).map_err(|e| e.to_string())?;
+    Ok((host, u16::from_be_bytes(port)))
+}
+
+fn socks5_reply(socket: &mut TcpStream, code: u8) -> std::io::Result<()> {
+    socket.write_all(&[5, code, 0, 1, 0, 0, 0, 0, 0, 0])
+}
+
+fn serve_socks(session: Session, mut socket: TcpStream, peer: SocketAddr, state: Arc<TunnelState>) {
+    let _ = socket.set_nonblocking(false);
+    let _ = socket.set_read_timeout(Some(SOCKS_HANDSHAKE_TIMEOUT));
+    let (host, port) = match socks5_handshake(&mut socket) {
+        Ok(target) => target,
+        Err(err) => {
+            state.report(format!("socks handshake error:{}", err));
+            return;
+        }
+    };
+    let channel = match open_direct_channel(&session, &host, port, peer) {
+        Ok(channel) => channel,
+        Err(err) => {
+            state.report(err);
+            let _ = socks5_reply(&mut socket, 5);
+            return;
+        }
+    };
+    if socks5_reply(&mut socket, 0).is_err() {
+        return;
+    }
+    let _ = socket.set_read_timeout(None);
+    serve_connection(session, channel, socket, state);
+}
+
+#[tauri::command]
+pub async fn start_local_forward(
+    app: AppHandle,
+    session_key: String,
+    bind_host: Option<String>,
+    bind_port: u16,
+    target_host: String,
+    target_port: u16,
+) -> Result<TunnelInfo, String> {
+    let session = get_session(&session_key)?;
+    let bind_host = bind_host.unwrap_or_else(|| String::from(DEFAULT_BIND_HOST));
+    let (listener, bind_port) = bind_local(&bind_host, bind_port)?;
+    let (info, state) = register_tunnel(
+        new_tunnel_info(
+            session_key,
+            KIND_LOCAL,
+            bind_host,
+            bind_port,
+            target_host.clone(),
+            target_port,
+        ),
+        app,
+    )?;
+    let accept_state = state.clone();
+    thread::spawn(move || {
+        accept_local(
+            listener,
+            accept_state,
+            move |socket, peer| match open_direct_channel(&session, &target_host, target_port, peer)
+            {
+                Ok(channel) => serve_connection(session.clone(), channel, socket, state.clone()),
+                Err(err) => state.report(err),
+            },
+        )
+    });
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn start_dynamic_forward(
+    app: AppHandle,
+    session_key: String,
+    bind_host: Option<String>,
+    bind_port: u16,
+) -> Result<TunnelInfo, String> {
+    let session = get_session(&session_key)?;
+    let bind_host = bind_host.unwrap_or_else(|| String::from(DEFAULT_BIND_HOST));
+    let (listener, bind_port) = bind_local(&bind_host, bind_port)?;
+    let (info, state) = register_tunnel(
+        new_tunnel_info(
+            session_key,
+            KIND_DYNAMIC,
+            bind_host,
+            bind_port,
+            String::new(),
+            0,
+        ),
+        app,
+    )?;
+    let accept_state = state.clone();
+    thread::spawn(move || {
+        accept_local(listener, accept_state, move |socket, peer| {
+            serve_socks(session.clone(), socket, peer, state.clone())
+        })
+    });
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn start_remote_forward(
+    app: AppHandle,
+    session_key: String,
+    remote_host: Option<String>,
+    remote_port: u16,
+    target_host: String,
+    target_port: u16,
+) -> Result<TunnelInfo, String> {
+    let session = get_session(&session_key)?;
+    let (listener, remote_port) = retry(&session, || {
+        session.channel_forward_listen(remote_port, remote_host.as_deref(), None)
+    })
+    .map_err(|e| format!("remote forward listen error:{}", e))?;
+    let mut listener = SessionHandle::new(&session, listener);
+    let (info, state) = register_tunnel(
+        new_tunnel_info(
+            session_key,
+            KIND_REMOTE,
+            remote_host.unwrap_or_else(|| String::from("localhost")),
+            remote_port,
+            target_host.clone(),
+            target_port,
+        ),
+        app,
+    )?;
+    thread::spawn(move || {
+        while !state.stopped() {
+            let accepted = attempt(&session, || listener.accept()).map_err(std::io::Error::from);
+            match accepted {
+                Ok(channel) => {
+                    let channel = SessionHandle::new(&session, channel);
+                    let session = session.clone();
+                    let state = state.clone();
+                    let target = format!("{}:{}", target_host, target_port);
+                    thread::spawn(move || match TcpStream::connect(&target) {
+                        Ok(socket) => serve_connection(session, channel, socket, state),
+                        Err(err) => state.report(format!("connect {} error:{}", target, err)),
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                Err(err) => state.fail(format!("accept error:{}", err)),
+            }
+        }
+        // dropping the listener cancels the forward on the server
+        drop(listener);
+    });
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn list_ssh_tunnels() -> Result<Vec<TunnelInfo>, String> {
+    let list = TUNNEL_MAP.lock().map_err(|e| e.to_string())?;
+    let mut tunnels: Vec<TunnelInfo> = list.values().map(|t| t.snapshot()).collect();
+    tunnels.sort_by_key(|t| t.created_at);
+    Ok(tunnels)
+}
+
+#[tauri::command]
+pub async fn stop_ssh_tunnel(id: String) -> Result<(), String> {
+    let mut list = TUNNEL_MAP.lock().map_err(|e| e.to_string())?;
+    let tunnel = list.remove(&id).ok_or("no tunnel")?;
+    tunnel.state.stop();
+    Ok(())
+}
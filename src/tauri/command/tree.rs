@@ -0,0 +1,250 @@
+use super::ssh::SftpClient;
+use super::ssh_exec::{exec_collect, shell_quote};
+use crate::toolbox::file::PathFilter;
+use ssh2::{FileStat, Session};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// how many paths go into one remote checksum call
+const SUM_BATCH_SIZE: usize = 100;
+
+pub struct TreeEntry {
+    pub relative: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub mode: u32,
+    pub atime: u64,
+    pub mtime: u64,
+}
+
+pub fn join_remote(root: &str, relative: &str) -> String {
+    format!("{}/{}", root.trim_end_matches('/'), relative)
+}
+
+#[cfg(unix)]
+pub fn local_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+pub fn local_mode(metadata: &fs::Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+#[cfg(unix)]
+pub fn set_local_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_local_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn seconds_since_epoch(time: std::io::Result<std::time::SystemTime>) -> u64 {
+    time.ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn set_local_times(path: &Path, atime: u64, mtime: u64) -> std::io::Result<()> {
+    let to_time = |secs: u64| std::time::UNIX_EPOCH + Duration::from_secs(secs);
+    let times = fs::FileTimes::new()
+        .set_accessed(to_time(atime))
+        .set_modified(to_time(mtime));
+    // directories can only be opened read-only for this on most platforms
+    fs::File::open(path)
+        .or_else(|_| fs::OpenOptions::new().write(true).open(path))?
+        .set_times(times)
+}
+
+pub fn walk_local(
+    root: &Path,
+    relative: &str,
+    filter: &PathFilter,
+    entries: &mut Vec<TreeEntry>,
+) -> Result<(), String> {
+    let dir = fs::read_dir(root.join(relative)).map_err(|e| format!("read dir error:{}", e))?;
+    for item in dir {
+        let item = item.map_err(|e| format!("read dir error:{}", e))?;
+        let name = item.file_name().to_string_lossy().to_string();
+        let child = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let mut metadata = match fs::symlink_metadata(item.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.file_type().is_symlink() {
+            // linked files are shipped as regular files, linked directories
+            // are skipped to stay clear of cycles
+            match fs::metadata(item.path()) {
+                Ok(target) if target.is_file() => metadata = target,
+                _ => continue,
+            }
+        }
+        let entry = TreeEntry {
+            relative: child.clone(),
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            mode: local_mode(&metadata),
+            atime: seconds_since_epoch(metadata.accessed()),
+            mtime: seconds_since_epoch(metadata.modified()),
+        };
+        if entry.is_dir {
+            if filter.accept_dir(&child) {
+                entries.push(entry);
+                walk_local(root, &child, filter, entries)?;
+            }
+        } else if metadata.is_file() && filter.accept_file(&child) {
+            entries.push(entry);
+        }
+    }
+    Ok(())
+}
+
+pub fn walk_remote(
+    sftp: &SftpClient,
+    root: &str,
+    relative: &str,
+    filter: &PathFilter,
+    entries: &mut Vec<TreeEntry>,
+) -> Result<(), String> {
+    let dir = if relative.is_empty() {
+        root.to_string()
+    } else {
+        join_remote(root, relative)
+    };
+    let list = sftp
+        .call(|s| s.readdir(Path::new(&dir)))
+        .map_err(|e| format!("read dir {} error:{}", dir, e))?;
+    for (path, mut stat) in list {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let child = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        if stat.file_type().is_symlink() {
+            // linked files are fetched as regular files, linked directories
+            // are skipped to stay clear of cycles
+            match sftp.call(|s| s.stat(&path)) {
+                Ok(target) if target.is_file() => stat = target,
+                _ => continue,
+            }
+        }
+        let entry = TreeEntry {
+            relative: child.clone(),
+            is_dir: stat.is_dir(),
+            size: stat.size.unwrap_or(0),
+            mode: stat.perm.unwrap_or(0) & 0o7777,
+            atime: stat.atime.unwrap_or(0),
+            mtime: stat.mtime.unwrap_or(0),
+        };
+        if entry.is_dir {
+            if filter.accept_dir(&child) {
+                entries.push(entry);
+                walk_remote(sftp, root, &child, filter, entries)?;
+            }
+        } else if stat.is_file() && filter.accept_file(&child) {
+            entries.push(entry);
+        }
+    }
+    Ok(())
+}
+
+pub fn sftp_create_dir_all(sftp: &SftpClient, path: &str, mode: u32) -> Result<(), String> {
+    let mut current = String::new();
+    for part in path.split('/') {
+        if part.is_empty() {
+            if current.is_empty() {
+                current.push('/');
+            }
+            continue;
+        }
+        if !current.is_empty() && !current.ends_with('/') {
+            current.push('/');
+        }
+        current.push_str(part);
+        let dir = Path::new(&current);
+        if sftp.call(|s| s.stat(dir)).is_err() {
+            sftp.call(|s| s.mkdir(dir, mode as i32))
+                .map_err(|e| format!("mkdir {} error:{}", current, e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn times_stat(entry: &TreeEntry) -> FileStat {
+    FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm: Some(entry.mode),
+        atime: Some(entry.atime),
+        mtime: Some(entry.mtime),
+    }
+}
+
+// `sha256sum` and friends prefix lines with a backslash when the name had to
+// be escaped
+fn parse_sums(output: &str) -> HashMap<String, String> {
+    let mut hashes = HashMap::new();
+    for line in output.lines() {
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let Some((hash, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let name = name.strip_prefix([' ', '*']).unwrap_or(name);
+        let name = if escaped {
+            name.replace("\\n", "\n").replace("\\\\", "\\")
+        } else {
+            name.to_string()
+        };
+        hashes.insert(name, hash.to_lowercase());
+    }
+    hashes
+}
+
+// hashes `paths` relative to `root` on the remote host with `<algorithm>sum`,
+// keyed by path; unreadable files are missing from the result
+pub fn remote_sums(
+    session: &Session,
+    algorithm: &str,
+    root: &str,
+    paths: &[&str],
+) -> Result<HashMap<String, String>, String> {
+    let mut hashes = HashMap::new();
+    for chunk in paths.chunks(SUM_BATCH_SIZE) {
+        let names: Vec<String> = chunk.iter().map(|path| shell_quote(path)).collect();
+        let command = format!(
+            "cd {} && {}sum -- {}",
+            shell_quote(root),
+            algorithm,
+            names.join(" ")
+        );
+        let output = exec_collect(session, &command, None)?;
+        if output.exit_status != 0 && output.stdout.is_empty() {
+            return Err(format!("{}sum error:{}", algorithm, output.stderr.trim()));
+        }
+        hashes.extend(parse_sums(&output.stdout));
+    }
+    Ok(hashes)
+}
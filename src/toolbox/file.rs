@@ -3,6 +3,10 @@ use std::{fs::read_dir, path::Path};
 use readable::byte::Byte;
 use base64::{engine::general_purpose, Engine as _};
 use mime_guess;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::io::Read;
 
 
 #[allow(dead_code)]
@@ -100,4 +104,67 @@ pub fn generate_jsonp_content(content_type: &str, input: &[u8], hash_code: &str)
             general_purpose::STANDARD.encode(input)
         ),
     }
-}
\ No newline at end of file
+}
+
+// include/exclude glob patterns matched against '/' separated relative paths
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<PathFilter, String> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(include)?)
+        };
+        Ok(PathFilter {
+            include,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    pub fn accept_dir(&self, relative: &str) -> bool {
+        !self.exclude.is_match(relative)
+    }
+
+    pub fn accept_file(&self, relative: &str) -> bool {
+        !self.exclude.is_match(relative)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+}
+
+fn digest_reader<D: Digest>(reader: &mut impl Read) -> Result<String, String> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let size = reader.read(&mut buffer).map_err(|e| e.to_string())?;
+        if size == 0 {
+            break;
+        }
+        hasher.update(&buffer[..size]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// lowercase hex digest of a file, `algorithm` is sha256 or md5
+pub fn file_hash(path: &Path, algorithm: &str) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("open {} error:{}", path.display(), e))?;
+    match algorithm {
+        "sha256" => digest_reader::<Sha256>(&mut file),
+        "md5" => digest_reader::<Md5>(&mut file),
+        _ => Err(format!("unsupported hash algorithm:{}", algorithm)),
+    }
+}
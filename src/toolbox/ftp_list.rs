@@ -0,0 +1,358 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+use std::time::UNIX_EPOCH;
+use suppaftp::list::{File, PosixPexQuery};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct FtpEntry {
+    pub name: String,
+    pub size: u64,
+    // unix seconds, 0 when the server did not say
+    pub modified: u64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub link_target: Option<String>,
+    // only known for unix style listings
+    pub mode: Option<u32>,
+    pub permissions: Option<String>,
+}
+
+// renders the low nine mode bits as `rwxr-xr-x`
+pub fn permission_string(mode: u32) -> String {
+    let mut text = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    text
+}
+
+// days since 1970-01-01 for a proleptic gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// the inverse of days_from_civil, as (year, month, day)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// MLSD times are `YYYYMMDDHHMMSS[.sss]` in UTC
+fn parse_mlsx_time(value: &str) -> Option<u64> {
+    let digits = value.split('.').next()?;
+    if digits.len() != 14 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| digits[range].parse::<i64>().ok();
+    let days = days_from_civil(field(0..4)?, field(4..6)?, field(6..8)?);
+    let seconds = days * 86400 + field(8..10)? * 3600 + field(10..12)? * 60 + field(12..14)?;
+    u64::try_from(seconds).ok()
+}
+
+// one MLSD line, `fact=value;fact=value; name`; the `.` and `..` entries
+// (type cdir and pdir) give None
+pub fn parse_mlsd_line(line: &str) -> Option<FtpEntry> {
+    let (facts, name) = line.trim_end_matches(['\r', '\n']).split_once(' ')?;
+    if name.is_empty() {
+        return None;
+    }
+    let mut entry = FtpEntry {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    for fact in facts.split(';') {
+        let Some((key, value)) = fact.split_once('=') else {
+            continue;
+        };
+        match key.to_lowercase().as_str() {
+            "type" => {
+                let kind = value.to_lowercase();
+                match kind.as_str() {
+                    "cdir" | "pdir" => return None,
+                    "dir" => entry.is_dir = true,
+                    _ if kind.starts_with("os.unix=slink")
+                        || kind.starts_with("os.unix=symlink") =>
+                    {
+                        entry.is_symlink = true;
+                        // vsftpd style `OS.unix=slink:/target`
+                        entry.link_target = value
+                            .split_once(':')
+                            .map(|(_, target)| target.to_string())
+                            .filter(|target| !target.is_empty());
+                    }
+                    _ => {}
+                }
+            }
+            "size" | "sizd" => entry.size = value.parse().unwrap_or(0),
+            "modify" => entry.modified = parse_mlsx_time(value).unwrap_or(0),
+            "unix.mode" => {
+                entry.mode = u32::from_str_radix(value, 8).ok().map(|mode| mode & 0o7777)
+            }
+            _ => {}
+        }
+    }
+    entry.permissions = entry.mode.map(permission_string);
+    Some(entry)
+}
+
+pub fn format_mlsx_time(time: u64) -> String {
+    let time = time as i64;
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    let seconds = time.rem_euclid(86400);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// the MLSD line parse_mlsd_line reads back
+pub fn format_mlsd_line(entry: &FtpEntry) -> String {
+    let mut facts = String::new();
+    if entry.is_dir {
+        facts.push_str("type=dir;");
+    } else {
+        facts.push_str(&format!("type=file;size={};", entry.size));
+    }
+    facts.push_str(&format!("modify={};", format_mlsx_time(entry.modified)));
+    if let Some(mode) = entry.mode {
+        facts.push_str(&format!("unix.mode={:04o};", mode));
+    }
+    format!("{} {}", facts, entry.name)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// an `ls -l` style line; like ls, entries from the last six months show the
+// time of day instead of the year
+pub fn format_list_line(entry: &FtpEntry, now: u64) -> String {
+    let kind = match (entry.is_dir, entry.is_symlink) {
+        (true, _) => 'd',
+        (false, true) => 'l',
+        _ => '-',
+    };
+    let default_mode = if entry.is_dir { 0o755 } else { 0o644 };
+    let permissions = permission_string(entry.mode.unwrap_or(default_mode));
+    let time = entry.modified as i64;
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    let date = match now.abs_diff(entry.modified) < 180 * 86400 {
+        true => {
+            let seconds = time.rem_euclid(86400);
+            format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60)
+        }
+        false => format!(" {}", year),
+    };
+    let mut name = entry.name.clone();
+    if let Some(target) = &entry.link_target {
+        name = format!("{} -> {}", name, target);
+    }
+    format!(
+        "{}{} 1 ftp ftp {:>12} {} {:02} {} {}",
+        kind,
+        permissions,
+        entry.size,
+        MONTHS[(month - 1) as usize],
+        day,
+        date,
+        name
+    )
+}
+
+fn posix_mode(file: &File) -> u32 {
+    let mut mode = 0;
+    for (shift, who) in [
+        (6, PosixPexQuery::Owner),
+        (3, PosixPexQuery::Group),
+        (0, PosixPexQuery::Others),
+    ] {
+        let bits = (file.can_read(who) as u32) << 2
+            | (file.can_write(who) as u32) << 1
+            | file.can_execute(who) as u32;
+        mode |= bits << shift;
+    }
+    mode
+}
+
+// one LIST line in unix `ls -l` or DOS/IIS format; totals and other noise
+// give None
+pub fn parse_list_line(line: &str) -> Option<FtpEntry> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (file, mode) = match File::from_posix_line(line) {
+        Ok(file) => {
+            let mode = posix_mode(&file);
+            (file, Some(mode))
+        }
+        Err(_) => (File::from_dos_line(line).ok()?, None),
+    };
+    if file.name() == "." || file.name() == ".." {
+        return None;
+    }
+    Some(FtpEntry {
+        name: file.name().to_string(),
+        size: file.size() as u64,
+        modified: file
+            .modified()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        is_dir: file.is_directory(),
+        is_symlink: file.is_symlink(),
+        link_target: file
+            .symlink()
+            .map(|target| target.to_string_lossy().to_string())
+            .filter(|target| !target.is_empty()),
+        permissions: mode.map(permission_string),
+        mode,
+    })
+}
+
+// names come from the server, so one has to be a single plain path component
+// before it is joined onto a local directory
+pub fn is_safe_entry_name(name: &str) -> bool {
+    let mut parts = Path::new(name).components();
+    let single = matches!((parts.next(), parts.next()), (Some(Component::Normal(_)), None));
+    single && !name.contains(['/', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mlsd_line() {
+        let entry = parse_mlsd_line(
+            "type=file;size=1024;modify=20240102030405.123;unix.mode=0644;UNIX.owner=1000; report 2024.csv",
+        )
+        .unwrap();
+        assert_eq!(entry.name, "report 2024.csv");
+        assert_eq!(entry.size, 1024);
+        assert_eq!(entry.modified, 1704164645);
+        assert_eq!(entry.permissions.as_deref(), Some("rw-r--r--"));
+        assert!(!entry.is_dir);
+
+        let dir = parse_mlsd_line("type=dir;modify=19700101000000; logs").unwrap();
+        assert!(dir.is_dir);
+        assert_eq!(dir.modified, 0);
+        assert_eq!(dir.mode, None);
+
+        let link = parse_mlsd_line("type=OS.unix=slink:/var/www;size=8; www").unwrap();
+        assert!(link.is_symlink);
+        assert_eq!(link.link_target.as_deref(), Some("/var/www"));
+
+        assert_eq!(parse_mlsd_line("type=cdir;modify=20240102030405; ."), None);
+        assert_eq!(parse_mlsd_line("type=pdir; .."), None);
+    }
+
+    #[test]
+    fn test_parse_list_line() {
+        let entry = parse_list_line("-rwxr-x--- 1 ftp ftp 2048 Jan 02 2024 deploy.sh\r").unwrap();
+        assert_eq!(entry.name, "deploy.sh");
+        assert_eq!(entry.size, 2048);
+        assert_eq!(entry.mode, Some(0o750));
+        assert_eq!(entry.permissions.as_deref(), Some("rwxr-x---"));
+
+        let link =
+            parse_list_line("lrwxrwxrwx 1 root root 7 Jan 02 2024 current -> v1.2.0").unwrap();
+        assert!(link.is_symlink);
+        assert_eq!(link.name, "current");
+        assert_eq!(link.link_target.as_deref(), Some("v1.2.0"));
+
+        let dos = parse_list_line("04-27-24  09:09PM       <DIR>          Backups").unwrap();
+        assert!(dos.is_dir);
+        assert_eq!(dos.name, "Backups");
+        assert_eq!(dos.mode, None);
+
+        let file = parse_list_line("04-27-24  09:09PM                 3072 notes.txt").unwrap();
+        assert_eq!(file.size, 3072);
+        assert!(!file.is_dir);
+
+        assert_eq!(parse_list_line("total 12"), None);
+    }
+
+    #[test]
+    fn test_hostile_listing_names() {
+        let mlsd = [
+            "type=file;size=1; ../../.bashrc",
+            "type=file;size=1; /etc/cron.d/job",
+            "type=dir; sub/../..",
+        ];
+        for line in mlsd {
+            assert!(!is_safe_entry_name(&parse_mlsd_line(line).unwrap().name), "{}", line);
+        }
+        let list = [
+            "-rw-r--r-- 1 ftp ftp 1 Jan 02 2024 ..\\..\\evil.bat",
+            "-rw-r--r-- 1 ftp ftp 1 Jan 02 2024 ../.ssh/authorized_keys",
+        ];
+        for line in list {
+            assert!(!is_safe_entry_name(&parse_list_line(line).unwrap().name), "{}", line);
+        }
+        assert_eq!(parse_list_line("-rw-r--r-- 1 ftp ftp 1 Jan 02 2024 .."), None);
+        assert!(is_safe_entry_name("report 2024.csv"));
+        assert!(is_safe_entry_name("..hidden"));
+        assert!(!is_safe_entry_name(""));
+        assert!(!is_safe_entry_name("."));
+    }
+
+    #[test]
+    fn test_format_lines() {
+        let entry = FtpEntry {
+            name: String::from("firmware v2.bin"),
+            size: 4096,
+            modified: 1704164645,
+            mode: Some(0o640),
+            permissions: Some(String::from("rw-r-----")),
+            ..Default::default()
+        };
+        assert_eq!(format_mlsx_time(entry.modified), "20240102030405");
+        assert_eq!(
+            format_mlsd_line(&entry),
+            "type=file;size=4096;modify=20240102030405;unix.mode=0640; firmware v2.bin"
+        );
+        assert_eq!(parse_mlsd_line(&format_mlsd_line(&entry)), Some(entry.clone()));
+
+        let line = format_list_line(&entry, 1735689600);
+        assert_eq!(
+            line,
+            "-rw-r----- 1 ftp ftp         4096 Jan 02  2024 firmware v2.bin"
+        );
+        let parsed = parse_list_line(&line).unwrap();
+        assert_eq!(parsed.name, entry.name);
+        assert_eq!(parsed.size, 4096);
+        assert_eq!(parsed.mode, Some(0o640));
+
+        let recent = format_list_line(&entry, entry.modified + 86400);
+        assert!(recent.contains(" Jan 02 03:04 "));
+
+        let dir = FtpEntry {
+            name: String::from("logs"),
+            is_dir: true,
+            ..Default::default()
+        };
+        assert!(parse_list_line(&format_list_line(&dir, 0)).unwrap().is_dir);
+        assert!(format_mlsd_line(&dir).starts_with("type=dir;"));
+    }
+}
@@ -3,3 +3,6 @@ pub mod zip;
 pub mod file;
 pub mod network;
 pub mod string;
+pub mod ssh_config;
+pub mod ftp_list;
+pub mod time;
\ No newline at end of file
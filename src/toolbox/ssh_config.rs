@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// OpenSSH gives up on include loops at the same depth
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// one Host block; `Match` blocks are kept with no patterns so they never apply
+struct HostBlock {
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SshHostProfile {
+    pub alias: String,
+    pub host_name: String,
+    pub user: Option<String>,
+    pub port: u16,
+    pub identity_files: Vec<String>,
+    pub proxy_jump: Vec<String>,
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => env::home_dir().unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+// `*` matches any run of characters and `?` exactly one, like ssh_config(5)
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|skip| wildcard_match(&pattern[1..], &text[skip..])),
+        Some('?') => !text.is_empty() && wildcard_match(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && wildcard_match(&pattern[1..], &text[1..]),
+    }
+}
+
+fn pattern_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    wildcard_match(&pattern, &text)
+}
+
+// a negated pattern that matches vetoes the whole block
+fn host_matches(patterns: &[String], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if pattern_match(negated, host) {
+                return false;
+            }
+        } else if pattern_match(pattern, host) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+// splits `Keyword value`, `Keyword=value` and `Keyword = value`, dropping
+// comments and surrounding quotes
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in rest.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && current.is_empty() => break,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    Some((keyword, args))
+}
+
+fn include_paths(pattern: &str, base_dir: &Path) -> Vec<PathBuf> {
+    let path = expand_tilde(pattern);
+    let path = if path.is_absolute() {
+        path
+    } else {
+        base_dir.join(path)
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !name.contains(['*', '?']) {
+        return vec![path];
+    }
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| pattern_match(&name, &entry.file_name().to_string_lossy()))
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+impl SshConfig {
+    pub fn parse(content: &str, base_dir: &Path) -> SshConfig {
+        let mut config = SshConfig {
+            // options before the first Host line apply to every host
+            blocks: vec![HostBlock {
+                patterns: vec![String::from("*")],
+                options: Vec::new(),
+            }],
+        };
+        config.read(content, base_dir, 0);
+        config
+    }
+
+    pub fn load(path: &Path) -> Result<SshConfig, String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("read {} error:{}", path.display(), e))?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Ok(SshConfig::parse(&content, base_dir))
+    }
+
+    pub fn default_path() -> Result<PathBuf, String> {
+        let home = env::home_dir().ok_or("no home dir")?;
+        Ok(home.join(".ssh").join("config"))
+    }
+
+    // included lines continue the block they appear in, as in OpenSSH
+    fn read(&mut self, content: &str, base_dir: &Path, depth: usize) {
+        for line in content.lines() {
+            let Some((keyword, args)) = split_line(line) else {
+                continue;
+            };
+            match keyword.as_str() {
+                "host" => self.blocks.push(HostBlock {
+                    patterns: args,
+                    options: Vec::new(),
+                }),
+                "match" => self.blocks.push(HostBlock {
+                    patterns: Vec::new(),
+                    options: Vec::new(),
+                }),
+                "include" if depth < MAX_INCLUDE_DEPTH => {
+                    let current = self.blocks.len() - 1;
+                    for pattern in args {
+                        for path in include_paths(&pattern, base_dir) {
+                            if let Ok(content) = fs::read_to_string(&path) {
+                                self.read(&content, base_dir, depth + 1);
+                                self.restore_block(current);
+                            }
+                        }
+                    }
+                }
+                "include" => {}
+                _ => {
+                    if let Some(block) = self.blocks.last_mut() {
+                        block.options.push((keyword, args.join(" ")));
+                    }
+                }
+            }
+        }
+    }
+
+    // a Host line inside an included file ends with that file; what follows
+    // belongs to the block the Include appeared in again
+    fn restore_block(&mut self, index: usize) {
+        if self.blocks.len() - 1 != index {
+            let patterns = self.blocks[index].patterns.clone();
+            self.blocks.push(HostBlock {
+                patterns,
+                options: Vec::new(),
+            });
+        }
+    }
+
+    // concrete aliases, i.e. Host patterns without wildcards or negation
+    pub fn aliases(&self) -> Vec<String> {
+        let mut aliases: Vec<String> = Vec::new();
+        for block in &self.blocks {
+            for pattern in &block.patterns {
+                if !pattern.contains(['*', '?', '!']) && !aliases.contains(pattern) {
+                    aliases.push(pattern.clone());
+                }
+            }
+        }
+        aliases
+    }
+
+    // the first value found for a keyword wins, except IdentityFile which
+    // accumulates
+    pub fn resolve(&self, alias: &str) -> SshHostProfile {
+        let mut host_name: Option<String> = None;
+        let mut user: Option<String> = None;
+        let mut port: Option<u16> = None;
+        let mut proxy_jump: Option<Vec<String>> = None;
+        let mut identity_files: Vec<String> = Vec::new();
+        for block in &self.blocks {
+            if !host_matches(&block.patterns, alias) {
+                continue;
+            }
+            for (keyword, value) in &block.options {
+                match keyword.as_str() {
+                    "hostname" if host_name.is_none() => host_name = Some(value.clone()),
+                    "user" if user.is_none() => user = Some(value.clone()),
+                    "port" if port.is_none() => port = value.parse().ok(),
+                    "identityfile" => identity_files.push(value.clone()),
+                    "proxyjump" if proxy_jump.is_none() => {
+                        proxy_jump = Some(if value.eq_ignore_ascii_case("none") {
+                            Vec::new()
+                        } else {
+                            value.split(',').map(|hop| hop.trim().to_string()).collect()
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let host_name = host_name
+            .map(|name| name.replace("%h", alias).replace("%%", "%"))
+            .unwrap_or_else(|| alias.to_string());
+        SshHostProfile {
+            alias: alias.to_string(),
+            host_name,
+            user,
+            port: port.unwrap_or(22),
+            identity_files: identity_files
+                .iter()
+                .map(|file| expand_tilde(file).to_string_lossy().to_string())
+                .collect(),
+            proxy_jump: proxy_jump.unwrap_or_default(),
+        }
+    }
+}
+
+// splits a ProxyJump hop `[user@]host[:port]`
+pub fn parse_jump(hop: &str) -> (Option<String>, String, Option<u16>) {
+    let (user, rest) = match hop.rsplit_once('@') {
+        Some((user, rest)) => (Some(user.to_string()), rest),
+        None => (None, hop),
+    };
+    let (host, port) = match rest.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, tail)) => (host, tail.strip_prefix(':')),
+            None => (rest, None),
+        },
+        None => match rest.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => (host, Some(port)),
+            _ => (rest, None),
+        },
+    };
+    (
+        user,
+        host.to_string(),
+        port.and_then(|port| port.parse().ok()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_first_value_wins() {
+        let config = SshConfig::parse(
+            "Host web\n  HostName 10.0.0.5\n  User deploy\n  IdentityFile /keys/web\n\nHost *\n  User root\n  Port 2222\n  IdentityFile /keys/default\n",
+            Path::new("/tmp"),
+        );
+        let profile = config.resolve("web");
+        assert_eq!(profile.host_name, "10.0.0.5");
+        assert_eq!(profile.user.as_deref(), Some("deploy"));
+        assert_eq!(profile.port, 2222);
+        assert_eq!(profile.identity_files, vec!["/keys/web", "/keys/default"]);
+        assert_eq!(config.aliases(), vec!["web"]);
+    }
+
+    #[test]
+    fn test_wildcard_and_negation() {
+        let config = SshConfig::parse(
+            "Host *.prod !bastion.prod\n  ProxyJump bastion.prod\nHost db?.prod\n  Port=5022\n",
+            Path::new("/tmp"),
+        );
+        assert_eq!(config.resolve("db1.prod").proxy_jump, vec!["bastion.prod"]);
+        assert_eq!(config.resolve("db1.prod").port, 5022);
+        assert!(config.resolve("bastion.prod").proxy_jump.is_empty());
+        assert_eq!(config.resolve("db10.prod").port, 22);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = env::temp_dir().join(format!("ssh_config_test_{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(
+            dir.join("conf.d").join("a.conf"),
+            "Host inc\n  HostName included.example\n",
+        )
+        .unwrap();
+        let config = SshConfig::parse("Include conf.d/*.conf\nHost other\n", &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.resolve("inc").host_name, "included.example");
+        assert_eq!(config.aliases(), vec!["inc", "other"]);
+    }
+
+    #[test]
+    fn test_include_keeps_enclosing_host() {
+        let dir = env::temp_dir().join(format!("ssh_config_block_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("other.conf"), "Host other\n  User nobody\n").unwrap();
+        let config = SshConfig::parse(
+            "Host web\n  Include other.conf\n  User deploy\n  Port 2200\n",
+            &dir,
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.resolve("web").user.as_deref(), Some("deploy"));
+        assert_eq!(config.resolve("web").port, 2200);
+        assert_eq!(config.resolve("other").user.as_deref(), Some("nobody"));
+        assert_eq!(config.resolve("other").port, 22);
+    }
+
+    #[test]
+    fn test_parse_jump() {
+        assert_eq!(parse_jump("bastion"), (None, String::from("bastion"), None));
+        assert_eq!(
+            parse_jump("ops@jump.example:2200"),
+            (
+                Some(String::from("ops")),
+                String::from("jump.example"),
+                Some(2200)
+            )
+        );
+        assert_eq!(
+            parse_jump("[::1]:22"),
+            (None, String::from("::1"), Some(22))
+        );
+    }
+}
@@ -1,6 +1,59 @@
 use md5::{Md5, Digest};
+use encoding_rs::{Encoding, GB18030, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
 
 pub fn md5_string(input: &str) -> String {
     let hash = Md5::digest(input.as_bytes());
     hex::encode(hash) // 返回小写十六进制字符串
-}
\ No newline at end of file
+}
+
+// decodes text with the given encoding label, or guesses one from the BOM,
+// utf-8 validity and GB18030 (the usual legacy encoding of our servers);
+// returns the text and the name of the encoding used
+pub fn decode_text(bytes: &[u8], label: Option<&str>) -> Result<(String, String), String> {
+    if let Some(label) = label.filter(|label| !label.is_empty()) {
+        let encoding = Encoding::for_label(label.as_bytes()).ok_or(format!("unknown encoding:{}", label))?;
+        let (text, used, _) = encoding.decode(bytes);
+        return Ok((text.to_string(), used.name().to_string()));
+    }
+    if let Some((encoding, bom)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom..]);
+        return Ok((text.to_string(), encoding.name().to_string()));
+    }
+    if bytes.contains(&0) {
+        return Err(String::from("binary file"));
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok((text.to_string(), UTF_8.name().to_string()));
+    }
+    if let Some(text) = GB18030.decode_without_bom_handling_and_without_replacement(bytes) {
+        return Ok((text.to_string(), GB18030.name().to_string()));
+    }
+    let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+    Ok((text.to_string(), WINDOWS_1252.name().to_string()))
+}
+
+// encoding_rs only encodes to ASCII-compatible encodings, utf-16 is written
+// by hand with a BOM
+pub fn encode_text(text: &str, label: Option<&str>) -> Result<Vec<u8>, String> {
+    let label = match label.filter(|label| !label.is_empty()) {
+        Some(label) => label,
+        None => return Ok(text.as_bytes().to_vec()),
+    };
+    let encoding = Encoding::for_label(label.as_bytes()).ok_or(format!("unknown encoding:{}", label))?;
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+        for unit in std::iter::once(0xfeff).chain(text.encode_utf16()) {
+            if encoding == UTF_16LE {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        return Ok(bytes);
+    }
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        return Err(format!("text cannot be encoded as {}", encoding.name()));
+    }
+    Ok(bytes.to_vec())
+}